@@ -0,0 +1,158 @@
+use crate::dgen_ast::*;
+use crate::lint::{Lint, LintConfig, LintLevels};
+use crate::optimizer::constant_condition;
+
+// The function whose body is being walked
+struct Function {
+    name: String,
+    return_type: Type,
+}
+
+/// Control-flow checks that only need the shape of the program: missing and misplaced returns,
+/// `break`/`continue` outside loops, and code that can never run.
+pub struct ControlFlow {
+    function: Option<Function>,
+    loop_depth: usize,
+    lints: LintLevels,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl ControlFlow {
+    pub fn check(program: &Stmt, config: &LintConfig) -> (Vec<String>, Vec<String>) {
+        let mut control_flow = Self { function: None, loop_depth: 0, lints: LintLevels::new(config.clone()), errors: Vec::new(), warnings: Vec::new() };
+        control_flow.visit(program);
+
+        (control_flow.errors, control_flow.warnings)
+    }
+
+    fn visit(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(stmts) | Stmt::Program(stmts) => self.visit_block(stmts),
+            Stmt::Return(value) => self.check_return(value.as_ref()),
+            Stmt::Break | Stmt::Continue if self.loop_depth == 0 => {
+                self.errors.push(format!("'{}' outside of a loop", exit_keyword(stmt)));
+            }
+            Stmt::If(_, if_block, else_block) => {
+                self.visit(if_block);
+                if let Some(else_block) = else_block {
+                    self.visit(else_block);
+                }
+            }
+            Stmt::For(_, _, _, block) | Stmt::While(_, block) => {
+                self.loop_depth += 1;
+                self.visit(block);
+                self.loop_depth -= 1;
+            }
+            Stmt::FuncDef(return_type, name, _, _, body) => self.visit_function(name, return_type.clone(), body),
+            Stmt::ClassDef(class, _, members) => {
+                for member in members {
+                    self.visit_member(class, member);
+                }
+            }
+            // Unknown attributes were already reported by the analyzer
+            Stmt::Attributed(attributes, stmt) => {
+                self.lints.enter(attributes);
+                self.visit(stmt);
+                self.lints.exit();
+            }
+            _ => (),
+        }
+    }
+
+    fn visit_member(&mut self, class: &str, member: &Stmt) {
+        match member {
+            Stmt::FuncDef(return_type, name, _, _, body) => {
+                // Constructors are declared with the class type but don't return anything
+                let return_type = if name == class { Type::Void } else { return_type.clone() };
+                self.visit_function(&format!("{}.{}", class, name), return_type, body);
+            }
+            Stmt::Attributed(attributes, member) => {
+                self.lints.enter(attributes);
+                self.visit_member(class, member);
+                self.lints.exit();
+            }
+            _ => (),
+        }
+    }
+
+    fn visit_block(&mut self, stmts: &[Stmt]) {
+        let mut exit = None;
+
+        for stmt in stmts {
+            if let Some(exit) = exit.take() {
+                let message = format!("Unreachable code after '{}'", exit_keyword(exit));
+                self.lints.report(Lint::UnreachableCode, &message, &mut self.errors, &mut self.warnings);
+            }
+
+            self.visit(stmt);
+
+            if !completes(stmt) {
+                exit = Some(stmt);
+            }
+        }
+    }
+
+    fn visit_function(&mut self, name: &str, return_type: Type, body: &Stmt) {
+        let outer_function  = self.function.replace(Function { name: name.to_string(), return_type });
+        let outer_depth     = std::mem::replace(&mut self.loop_depth, 0);
+
+        self.visit(body);
+
+        if let Some(Function { name, return_type }) = &self.function {
+            if *return_type != Type::Void && completes(body) {
+                self.errors.push(format!("Function '{}' must return a value of type {:?} on every path", name, return_type));
+            }
+        }
+
+        self.function   = outer_function;
+        self.loop_depth = outer_depth;
+    }
+
+    fn check_return(&mut self, value: Option<&Expr>) {
+        match (&self.function, value) {
+            (None, _) => self.errors.push("'return' outside of a function".to_string()),
+            (Some(Function { name, return_type: Type::Void }), Some(_)) => {
+                self.errors.push(format!("Void function '{}' cannot return a value", name));
+            }
+            (Some(Function { name, return_type }), None) if *return_type != Type::Void => {
+                self.errors.push(format!("Function '{}' must return a value of type {:?}", name, return_type));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Whether control can reach the end of `stmt`, rather than always leaving through a `return`,
+/// `break` or `continue` or looping forever.
+pub fn completes(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return(_) | Stmt::Break | Stmt::Continue      => false,
+        Stmt::Block(stmts) | Stmt::Program(stmts)           => stmts.iter().all(completes),
+        Stmt::If(_, if_block, Some(else_block))             => completes(if_block) || completes(else_block),
+        Stmt::While(cond, block) | Stmt::For(_, cond, _, block) if constant_condition(cond) == Some(true) => breaks(block),
+        Stmt::Attributed(_, stmt)                           => completes(stmt),
+        _                                                   => true,
+    }
+}
+
+// Whether a loop body contains a `break` for that loop, not counting nested loops
+fn breaks(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Break                         => true,
+        Stmt::Block(stmts)                  => stmts.iter().any(breaks),
+        Stmt::If(_, if_block, else_block)   => breaks(if_block) || else_block.as_deref().is_some_and(breaks),
+        Stmt::Attributed(_, stmt)           => breaks(stmt),
+        _                                   => false,
+    }
+}
+
+fn exit_keyword(stmt: &Stmt) -> &'static str {
+    match stmt.unattributed() {
+        Stmt::Return(_)     => "return",
+        Stmt::Break         => "break",
+        Stmt::Continue      => "continue",
+        Stmt::If(..)        => "if",
+        _                   => "loop",
+    }
+}
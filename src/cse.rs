@@ -0,0 +1,290 @@
+use std::collections::HashSet;
+
+use crate::dgen_ast::*;
+use crate::generic::*;
+use crate::boxable::Boxable;
+use crate::inlining::all_names;
+use crate::prelude::{self, Effect};
+use crate::propagation::{calls_user_code, escaping_names, stepped_names};
+
+/// Computes expressions that are repeated within a run of statements without branches or loops
+/// once, into a variable declared before the first statement using them. Only expressions whose
+/// value can't change in between are shared: pure builtins, user functions that compute their
+/// result from their arguments alone, and variables nothing in between assigns.
+pub fn eliminate(program: Stmt) -> Stmt {
+    let Stmt::Program(stmts) = program else { return program };
+    let mut cse = Cse { pure_functions: pure_functions(&stmts), escaping: escaping_names(&stmts), taken: all_names(&stmts) };
+
+    Stmt::Program(cse.block(stmts))
+}
+
+// Expressions with fewer nodes than this, like `!x` or `a[i]`, cost about as much as reading a variable
+const MIN_SIZE: usize = 3;
+
+struct Cse {
+    // Top-level functions that only compute a value from their arguments
+    pure_functions: HashSet<String>,
+    // Variables some function assigns without declaring them, which any call to user code may change
+    escaping: HashSet<String>,
+    // Names in use anywhere in the program, so the new variables can't clash with them
+    taken: HashSet<String>,
+}
+
+impl Cse {
+    // The statements with the nested blocks handled on their own, then the repeats of every run shared
+    fn block(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        let mut result  = Vec::new();
+        let mut run     = Vec::new();
+
+        for stmt in stmts {
+            match self.nested(stmt) {
+                stmt @ (Stmt::Expr(_) | Stmt::VarDecl(..) | Stmt::ArrayDecl(..) | Stmt::Assign(..) | Stmt::MemberAssign(..)) => run.push(stmt),
+                // The condition of an `if` and the value of a `return` are the last thing the run computes
+                stmt @ (Stmt::If(..) | Stmt::Return(_)) => {
+                    run.push(stmt);
+                    result.extend(self.run(std::mem::take(&mut run)));
+                },
+                other => {
+                    result.extend(self.run(std::mem::take(&mut run)));
+                    result.push(other);
+                },
+            }
+        }
+
+        result.extend(self.run(run));
+        result
+    }
+
+    fn nested(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Block(stmts)                  => Stmt::Block(self.block(stmts)),
+            Stmt::If(cond, if_block, else_block) => Stmt::If(cond, self.nested(*if_block).wrap(), else_block.map(|else_block| self.nested(*else_block).wrap())),
+            Stmt::While(cond, block)            => Stmt::While(cond, self.nested(*block).wrap()),
+            Stmt::For(init, cond, step, block)  => Stmt::For(init, cond, step, self.nested(*block).wrap()),
+            Stmt::FuncDef(return_type, name, type_params, params, body) => Stmt::FuncDef(return_type, name, type_params, params, self.nested(*body).wrap()),
+            Stmt::ClassDef(name, base, members) => Stmt::ClassDef(name, base, members.into_iter().map(|member| self.nested(member)).collect()),
+            Stmt::Attributed(attributes, stmt)  => Stmt::Attributed(attributes, self.nested(*stmt).wrap()),
+            other                               => other,
+        }
+    }
+
+    // A run of statements that always execute one after the other, with its repeats shared, largest first
+    fn run(&mut self, mut stmts: Vec<Stmt>) -> Vec<Stmt> {
+        while let Some((expr, range)) = self.repeated(&stmts) {
+            let name = match &expr {
+                Expr::FuncCall(function, _) => self.fresh(function),
+                _                           => self.fresh("common"),
+            };
+
+            for stmt in &mut stmts[range.clone()] {
+                *stmt = replace_in(std::mem::replace(stmt, Stmt::Break), &expr, &name);
+            }
+            // The declaration is part of the run, what it repeats of a later statement is shared in turn
+            stmts.insert(range.start, Stmt::VarDecl(Type::Any, name, Some(expr)));
+        }
+
+        stmts
+    }
+
+    // The largest expression worth sharing that is computed at least twice while what it reads stays
+    // the same, with the statements that range covers
+    fn repeated(&self, stmts: &[Stmt]) -> Option<(Expr, std::ops::Range<usize>)> {
+        let mut candidates: Vec<Expr> = Vec::new();
+
+        for stmt in stmts {
+            for expr in evaluated(stmt) {
+                expr.walk(&mut |expr| if self.is_shareable(expr) && !candidates.contains(expr) {
+                    candidates.push(expr.clone());
+                });
+            }
+        }
+
+        candidates.sort_by_key(|expr| std::cmp::Reverse(size(expr)));
+
+        candidates.into_iter().find_map(|expr| self.live_range(&expr, stmts).map(|range| (expr, range)))
+    }
+
+    // The first stretch of statements where the expression is computed twice and keeps its value
+    fn live_range(&self, expr: &Expr, stmts: &[Stmt]) -> Option<std::ops::Range<usize>> {
+        let reads       = read_variables(expr);
+        let mut start   = None;
+        let mut uses    = 0;
+
+        for (index, stmt) in stmts.iter().enumerate() {
+            let exprs = evaluated(stmt);
+
+            // What the statement changes while it runs makes the occurrences in it differ
+            let steps = exprs.iter().any(|e| !stepped_names(e).is_disjoint(&reads) || (calls_user_code(e) && !self.escaping.is_disjoint(&reads)));
+            if steps {
+                if uses > 1 {
+                    return start.map(|start| start..index);
+                }
+                (start, uses) = (None, 0);
+                continue;
+            }
+
+            let count: usize = exprs.iter().map(|e| occurrences(e, expr)).sum();
+            if count > 0 {
+                start.get_or_insert(index);
+                uses += count;
+            }
+
+            // Its value is computed before the statement assigns, the following ones see the new one
+            let assigns = matches!(stmt, Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) | Stmt::Assign(name, _) if reads.contains(name));
+            if assigns || index == stmts.len() - 1 {
+                if uses > 1 {
+                    return start.map(|start| start..index + 1);
+                }
+                (start, uses) = (None, 0);
+            }
+        }
+
+        None
+    }
+
+    // Does enough to be worth a variable, a call or two operations, and gives the same value wherever
+    // nothing it reads changed
+    fn is_shareable(&self, expr: &Expr) -> bool {
+        if matches!(expr, Expr::Array(_) | Expr::NamedArg(..) | Expr::Spread(_)) || (size(expr) < MIN_SIZE && !matches!(expr, Expr::FuncCall(..))) {
+            return false;
+        }
+
+        let mut pure = true;
+
+        expr.walk(&mut |expr| pure &= match expr {
+            Expr::UnaryOp(Operator::Inc | Operator::Dec, _, _)  => false,
+            Expr::Identifier(name)                              => prelude::find(name).is_none_or(|builtin| builtin.effect == Effect::Pure),
+            Expr::FuncCall(name, _)                             => match prelude::find(name) {
+                Some(builtin)   => builtin.effect == Effect::Pure,
+                None            => self.pure_functions.contains(name),
+            },
+            // Fields can change through any method or member assignment
+            Expr::New(..) | Expr::MethodCall(..) | Expr::Member(..) => false,
+            _                                                   => true,
+        });
+
+        pure
+    }
+
+    fn fresh(&mut self, name: &str) -> String {
+        let fresh = (1..).map(|n| format!("{}_{}", name, n)).find(|fresh| !self.taken.contains(fresh)).unwrap();
+        self.taken.insert(fresh.clone());
+        fresh
+    }
+}
+
+// Top-level functions whose result only depends on their arguments: they assign, step and read
+// nothing but their parameters and locals, and call only pure builtins and other such functions.
+// A recursive function can be one, it's assumed to be and ruled out if something else disagrees.
+fn pure_functions(program: &[Stmt]) -> HashSet<String> {
+    let functions: Vec<(&String, HashSet<String>, &Stmt)> = program.iter().filter_map(|stmt| match stmt.unattributed() {
+        Stmt::FuncDef(_, name, _, params, body) => {
+            let mut locals: HashSet<String> = params.iter().map(|(_, name, _)| name.clone()).collect();
+
+            body.walk_local(&mut |stmt| if let Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) = stmt {
+                locals.insert(name.clone());
+            });
+            Some((name, locals, &**body))
+        },
+        _ => None,
+    }).collect();
+
+    let mut pure: HashSet<String> = functions.iter().map(|(name, _, _)| (*name).clone()).collect();
+
+    loop {
+        let impure: Vec<String> = functions.iter()
+            .filter(|(name, locals, body)| pure.contains(*name) && !computes_only(body, locals, &pure))
+            .map(|(name, _, _)| (*name).clone())
+            .collect();
+
+        if impure.is_empty() {
+            return pure;
+        }
+        for name in impure {
+            pure.remove(&name);
+        }
+    }
+}
+
+fn computes_only(body: &Stmt, locals: &HashSet<String>, pure: &HashSet<String>) -> bool {
+    let mut only = true;
+
+    body.walk_local(&mut |stmt| {
+        only &= match stmt {
+            Stmt::Assign(name, _)                   => locals.contains(name),
+            Stmt::MemberAssign(..) | Stmt::FuncDef(..) | Stmt::ClassDef(..) => false,
+            _                                       => true,
+        };
+
+        for expr in stmt.exprs() {
+            expr.walk(&mut |expr| only &= match expr {
+                Expr::Identifier(name)              => locals.contains(name) || prelude::find(name).is_some_and(|builtin| builtin.effect == Effect::Pure),
+                Expr::ArrayAccess(name, _)          => locals.contains(name),
+                Expr::FuncCall(name, _)             => match prelude::find(name) {
+                    Some(builtin)   => builtin.effect == Effect::Pure,
+                    None            => pure.contains(name),
+                },
+                Expr::New(..) | Expr::MethodCall(..) | Expr::Member(..) => false,
+                _                                   => true,
+            });
+        }
+    });
+
+    only
+}
+
+// The expressions a statement of a run computes; only the condition of an `if`, its branches are runs of their own
+fn evaluated(stmt: &Stmt) -> Vec<&Expr> {
+    match stmt {
+        Stmt::If(cond, _, _)    => vec![cond],
+        stmt                    => stmt.exprs(),
+    }
+}
+
+fn replace_in(stmt: Stmt, target: &Expr, name: &str) -> Stmt {
+    let replace = |expr: Expr| replace(expr, target, name);
+
+    match stmt {
+        Stmt::Expr(expr)                        => Stmt::Expr(replace(expr)),
+        Stmt::VarDecl(typename, var, value)     => Stmt::VarDecl(typename, var, value.map(replace)),
+        Stmt::ArrayDecl(typename, var, value)   => Stmt::ArrayDecl(typename, var, value.map(replace)),
+        Stmt::Assign(var, value)                => Stmt::Assign(var, replace(value)),
+        Stmt::MemberAssign(object, field, value) => Stmt::MemberAssign(replace(object), field, replace(value)),
+        Stmt::Return(value)                     => Stmt::Return(value.map(replace)),
+        Stmt::If(cond, if_block, else_block)    => Stmt::If(replace(cond), if_block, else_block),
+        other                                   => other,
+    }
+}
+
+fn replace(expr: Expr, target: &Expr, name: &str) -> Expr {
+    if expr == *target {
+        return Expr::Identifier(name.to_string());
+    }
+    expr.map_children(&mut |child| replace(child, target, name))
+}
+
+fn occurrences(expr: &Expr, target: &Expr) -> usize {
+    let mut count = 0;
+
+    expr.walk(&mut |expr| count += (expr == target) as usize);
+
+    count
+}
+
+fn read_variables(expr: &Expr) -> HashSet<String> {
+    let mut names = HashSet::new();
+
+    expr.walk(&mut |expr| if let Expr::Identifier(name) | Expr::ArrayAccess(name, _) = expr {
+        names.insert(name.clone());
+    });
+
+    names
+}
+
+fn size(expr: &Expr) -> usize {
+    let mut size = 0;
+
+    expr.walk(&mut |_| size += 1);
+
+    size
+}
@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+
+use crate::dgen_ast::*;
+use crate::boxable::Boxable;
+use crate::control_flow;
+use crate::optimizer::{constant_condition, has_side_effects};
+
+// Attribute that keeps a function that nothing in the program calls, for other scripts to use
+const EXPORT: &str = "export";
+
+/// Removes code that can never run and code that only computes values nobody reads: constant
+/// `if`/`while` branches, statements after a `return`, `break` or `continue`, variables that are
+/// only ever set to pure values and top-level functions that aren't called or exported.
+pub fn eliminate(program: Stmt) -> Stmt {
+    let Stmt::Program(stmts) = program else { return program };
+    let mut stmts: Vec<Stmt> = stmts.into_iter().flat_map(prune).collect();
+
+    // Dropping a declaration or a function can leave what it read unused in turn
+    loop {
+        let next = remove_unused_variables(remove_unused_functions(stmts.clone()));
+
+        if next == stmts {
+            return Stmt::Program(stmts);
+        }
+        stmts = next;
+    }
+}
+
+// A statement without its dead branches; an `if` on a constant becomes the statements of the branch taken
+fn prune(stmt: Stmt) -> Vec<Stmt> {
+    match stmt {
+        Stmt::If(cond, if_block, else_block) => match constant_condition(&cond) {
+            Some(true)  => prune_block(*if_block),
+            Some(false) => else_block.map(|else_block| prune_block(*else_block)).unwrap_or_default(),
+            None        => {
+                let if_block    = prune_body(*if_block);
+                // An empty `else` does nothing, and neither does an `if` with nothing in it but its condition
+                let else_block  = else_block.map(|else_block| prune_body(*else_block)).filter(|else_block| *else_block != Stmt::Block(Vec::new()));
+
+                match (&if_block, &else_block) {
+                    (Stmt::Block(stmts), None) if stmts.is_empty() && !has_side_effects(&cond) => Vec::new(),
+                    _ => vec![Stmt::If(cond, if_block.wrap(), else_block.map(|else_block| else_block.wrap()))],
+                }
+            },
+        },
+        Stmt::While(cond, _) if constant_condition(&cond) == Some(false) => Vec::new(),
+        Stmt::While(cond, block) => vec![Stmt::While(cond, prune_body(*block).wrap())],
+        // The initializer still runs once
+        Stmt::For(init, cond, _, _) if constant_condition(&cond) == Some(false) => vec![*init],
+        Stmt::For(init, cond, step, block) => vec![Stmt::For(init, cond, step, prune_body(*block).wrap())],
+        Stmt::Block(_) => vec![prune_body(stmt)],
+        Stmt::FuncDef(return_type, name, type_params, params, body) => vec![Stmt::FuncDef(return_type, name, type_params, params, prune_body(*body).wrap())],
+        Stmt::ClassDef(name, base, members) => vec![Stmt::ClassDef(name, base, members.into_iter().flat_map(prune).collect())],
+        Stmt::Attributed(attributes, stmt) => prune(*stmt).into_iter().map(|stmt| Stmt::Attributed(attributes.clone(), stmt.wrap())).collect(),
+        other => vec![other],
+    }
+}
+
+// Like `prune`, for statements that have to stay a single block
+fn prune_body(stmt: Stmt) -> Stmt {
+    Stmt::Block(prune_block(stmt))
+}
+
+// The statements of a block, up to the first one that never completes
+fn prune_block(stmt: Stmt) -> Vec<Stmt> {
+    let stmts = match stmt {
+        Stmt::Block(stmts)  => stmts,
+        other               => vec![other],
+    };
+    let mut pruned = Vec::new();
+
+    for stmt in stmts.into_iter().flat_map(prune) {
+        let exits = !control_flow::completes(&stmt);
+        pruned.push(stmt);
+
+        if exits {
+            break;
+        }
+    }
+
+    pruned
+}
+
+fn remove_unused_functions(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    let reachable = reachable_functions(&stmts);
+
+    stmts.into_iter().filter(|stmt| match stmt.unattributed() {
+        Stmt::FuncDef(_, name, ..) | Stmt::FuncDecl(_, name, _) => reachable.contains(name),
+        _ => true,
+    }).collect()
+}
+
+// Functions the top-level code and the exported functions call, directly or through other functions
+fn reachable_functions(stmts: &[Stmt]) -> HashSet<String> {
+    let functions: Vec<(&String, &Stmt)> = stmts.iter().filter_map(|stmt| match stmt.unattributed() {
+        Stmt::FuncDef(_, name, ..) => Some((name, stmt)),
+        _ => None,
+    }).collect();
+
+    let exported        = functions.iter().filter(|(_, function)| function.has_attribute(EXPORT)).map(|(name, _)| name.to_string());
+    let roots           = stmts.iter().filter(|stmt| !matches!(stmt.unattributed(), Stmt::FuncDef(..)));
+    let mut reachable   = HashSet::new();
+    let mut pending     = exported.chain(roots.flat_map(read_names)).collect::<Vec<String>>();
+
+    while let Some(name) = pending.pop() {
+        if !reachable.insert(name.clone()) {
+            continue;
+        }
+
+        for (_, function) in functions.iter().filter(|(function, _)| **function == name) {
+            pending.extend(read_names(function));
+        }
+    }
+
+    reachable
+}
+
+// Variables, functions and arrays the statement reads, calls or passes around
+pub fn read_names(stmt: &Stmt) -> Vec<String> {
+    let mut names = Vec::new();
+
+    stmt.walk_exprs(&mut |expr| match expr {
+        Expr::Identifier(name) | Expr::ArrayAccess(name, _) | Expr::FuncCall(name, _) => names.push(name.clone()),
+        _ => (),
+    });
+
+    names
+}
+
+// Variables nothing reads are dropped along with their assignments, as long as none of them has side effects
+fn remove_unused_variables(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    let mut declared    = HashSet::new();
+    let mut needed      = HashSet::new();
+
+    for stmt in &stmts {
+        needed.extend(read_names(stmt));
+
+        stmt.walk(&mut |stmt| match stmt {
+            Stmt::VarDecl(_, name, value) | Stmt::ArrayDecl(_, name, value) => {
+                declared.insert(name.clone());
+                if value.as_ref().is_some_and(has_side_effects) {
+                    needed.insert(name.clone());
+                }
+            }
+            Stmt::Assign(name, value) if has_side_effects(value) => {
+                needed.insert(name.clone());
+            }
+            // A loop's own variable stays, the loop can't do without it
+            Stmt::For(init, ..) => if let Stmt::VarDecl(_, name, _) = &**init {
+                needed.insert(name.clone());
+            },
+            _ => (),
+        });
+    }
+
+    let unused: HashSet<String> = declared.difference(&needed).cloned().collect();
+    stmts.into_iter().filter_map(|stmt| remove_writes(stmt, &unused)).collect()
+}
+
+fn remove_writes(stmt: Stmt, unused: &HashSet<String>) -> Option<Stmt> {
+    let remove_all = |stmts: Vec<Stmt>| stmts.into_iter().filter_map(|stmt| remove_writes(stmt, unused)).collect();
+    let remove_in  = |stmt: Box<Stmt>| remove_writes(*stmt, unused).unwrap_or(Stmt::Block(Vec::new())).wrap();
+
+    match stmt {
+        Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) | Stmt::Assign(name, _) if unused.contains(&name) => None,
+        Stmt::Block(stmts)                          => Some(Stmt::Block(remove_all(stmts))),
+        Stmt::If(cond, if_block, else_block)        => Some(Stmt::If(cond, remove_in(if_block), else_block.map(remove_in))),
+        Stmt::While(cond, block)                    => Some(Stmt::While(cond, remove_in(block))),
+        Stmt::For(init, cond, step, block)          => Some(Stmt::For(init, cond, step, remove_in(block))),
+        Stmt::FuncDef(return_type, name, type_params, params, body) => Some(Stmt::FuncDef(return_type, name, type_params, params, remove_in(body))),
+        Stmt::ClassDef(name, base, members)         => Some(Stmt::ClassDef(name, base, members.into_iter().map(|member| match member.unattributed() {
+            // Fields aren't variables, they are read through `self`
+            Stmt::VarDecl(..) => member,
+            _ => remove_writes(member, unused).unwrap_or(Stmt::Block(Vec::new())),
+        }).collect())),
+        Stmt::Attributed(attributes, stmt)          => remove_writes(*stmt, unused).map(|stmt| Stmt::Attributed(attributes, stmt.wrap())),
+        other                                       => Some(other),
+    }
+}
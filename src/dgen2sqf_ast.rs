@@ -3,6 +3,9 @@ use crate::generic::*;
 use crate::dgen_ast;
 use crate::sqf_ast;
 use crate::boxable::*;
+use crate::prelude;
+use crate::loops::{self, LoopFacts};
+use crate::narrowing;
 
 impl Transform<Vec<sqf_ast::Stmt>> for Vec<dgen_ast::Stmt> {
     fn transform(&self) -> Vec<sqf_ast::Stmt> {
@@ -16,9 +19,9 @@ impl Transform<Vec<sqf_ast::Expr>> for Vec<dgen_ast::Expr> {
     }
 }
 
-impl Transform<Vec<(sqf_ast::Type, String)>> for Vec<(dgen_ast::Type, String)> {
-    fn transform(&self) -> Vec<(sqf_ast::Type, String)> {
-        self.iter().map(|s| (s.0.transform(), format!("_{}", s.1))).collect()
+impl Transform<Vec<(sqf_ast::Type, String, Option<sqf_ast::Expr>)>> for Vec<(dgen_ast::Type, String, Option<dgen_ast::Expr>)> {
+    fn transform(&self) -> Vec<(sqf_ast::Type, String, Option<sqf_ast::Expr>)> {
+        self.iter().map(|s| (s.0.transform(), format!("_{}", s.1), s.2.transform())).collect()
     }
 }
 
@@ -54,26 +57,127 @@ impl Transform<Box<sqf_ast::Expr>> for Box<dgen_ast::Expr> {
 
 impl Transform<sqf_ast::Stmt> for dgen_ast::Stmt {
     fn transform(&self) -> sqf_ast::Stmt {
-        match self {
-            dgen_ast::Stmt::Expr(e)                                     => sqf_ast::Stmt::Expr(e.transform()),
-            dgen_ast::Stmt::VarDecl(_, name, value)                     => sqf_ast::Stmt::VarDecl(format!("_{}", name), value.transform()),
+        // A statement lowered on its own is all of the program there is
+        let program = match self {
+            dgen_ast::Stmt::Program(stmts)  => stmts.as_slice(),
+            stmt                            => std::slice::from_ref(stmt),
+        };
+
+        Lowering { loops: LoopFacts::of(program) }.stmt(self)
+    }
+}
+
+// Lowers the statements of a program, knowing what its loops need to know about the rest of it
+struct Lowering {
+    loops: LoopFacts,
+}
+
+impl Lowering {
+    fn stmts(&self, stmts: &[dgen_ast::Stmt]) -> Vec<sqf_ast::Stmt> {
+        stmts.iter().map(|stmt| self.stmt(stmt)).collect()
+    }
+
+    fn stmt(&self, stmt: &dgen_ast::Stmt) -> sqf_ast::Stmt {
+        match stmt {
+            // `++` and `--` only compute the new value in an expression, as a statement they store it
+            dgen_ast::Stmt::Expr(e)                                     => match stepped_variable(e) {
+                                                                                Some((name, _)) => sqf_ast::Stmt::Assign(format!("_{}", name), e.transform()),
+                                                                                None            => sqf_ast::Stmt::Expr(e.transform()),
+                                                                            },
+            dgen_ast::Stmt::VarDecl(typename, name, value)              => sqf_ast::Stmt::VarDecl(format!("_{}", name), value.transform().or_else(|| null_default(typename))),
             dgen_ast::Stmt::Assign(name, value)                         => sqf_ast::Stmt::Assign(format!("_{}", name), value.transform()),
-            dgen_ast::Stmt::Block(v)                                    => sqf_ast::Stmt::Block(v.transform()),
-            dgen_ast::Stmt::Program(v)                                  => sqf_ast::Stmt::Program(v.transform()),
-            dgen_ast::Stmt::FuncDef(_, name, params, body)              => sqf_ast::Stmt::FuncDef(format!("_{}", name), params.transform(), body.transform()),
+            dgen_ast::Stmt::MemberAssign(target, field, value)          => sqf_ast::Stmt::Expr(sqf_ast::Expr::BinaryCmd(
+                                                                                "set".to_string(),
+                                                                                target.transform().wrap(),
+                                                                                sqf_ast::Expr::Array(vec![sqf_ast::Expr::String(field.clone()), value.transform()]).wrap()
+                                                                            )),
+            dgen_ast::Stmt::ClassDef(name, base, members)               => sqf_ast::Stmt::ClassDef(format!("_{}", name), self.class_entries(name, base, members)),
+            dgen_ast::Stmt::Block(v)                                    => sqf_ast::Stmt::Block(self.stmts(v)),
+            dgen_ast::Stmt::Program(v)                                  => sqf_ast::Stmt::Program(self.stmts(&hoist_functions(v))),
+            dgen_ast::Stmt::FuncDef(_, name, _, params, body)           => {
+                let (params, body) = function_code(params, self.stmt(body));
+                sqf_ast::Stmt::FuncDef(format!("_{}", name), params, body.wrap())
+            }
             dgen_ast::Stmt::Return(e)                                   => sqf_ast::Stmt::Return(e.transform()),
             dgen_ast::Stmt::Break                                       => sqf_ast::Stmt::Break,
             dgen_ast::Stmt::Continue                                    => sqf_ast::Stmt::Continue,
-            dgen_ast::Stmt::If(condition, if_block, else_block)         => sqf_ast::Stmt::If(condition.transform(), if_block.transform(), else_block.transform()),
-            dgen_ast::Stmt::For(init, condition, step, block)           => sqf_ast::Stmt::For(init.transform(), condition.transform(), step.transform(), block.transform()),
-            dgen_ast::Stmt::While(condition, block)                     => sqf_ast::Stmt::While(condition.transform(), block.transform()),
+            dgen_ast::Stmt::If(condition, if_block, else_block)         => sqf_ast::Stmt::If(condition.transform(), self.stmt(if_block).wrap(), else_block.as_ref().map(|else_block| self.stmt(else_block).wrap())),
+            dgen_ast::Stmt::For(init, condition, step, block)           => match loops::counting_loop(init, condition, step, block, &self.loops) {
+                                                                                Some(counting)  => self.counting_loop(counting, block),
+                                                                                None            => sqf_ast::Stmt::For(self.stmt(init).wrap(), condition.transform(), self.stmt(step).wrap(), self.stmt(block).wrap()),
+                                                                            },
+            dgen_ast::Stmt::While(condition, block)                     => sqf_ast::Stmt::While(condition.transform(), self.stmt(block).wrap()),
             dgen_ast::Stmt::FuncDecl { .. }                             => sqf_ast::Stmt::Dummy, // SQF doesn't support function declarations
+            dgen_ast::Stmt::Attributed(_, stmt)                         => self.stmt(stmt),
             _ => {
-                println!("Can't convert {:?} to sqf_ast::Stmt", self);
+                println!("Can't convert {:?} to sqf_ast::Stmt", stmt);
                 sqf_ast::Stmt::Dummy
             }
         }
     }
+
+    /// Lowers a loop counting a number up by a constant to a bound into `for "_i" from a to b step c`,
+    /// which SQF runs much faster than `for [{init},{cond},{step}]`.
+    fn counting_loop(&self, counting: loops::CountingLoop, block: &dgen_ast::Stmt) -> sqf_ast::Stmt {
+        let step = counting.step.map(sqf_ast::Expr::Number);
+        sqf_ast::Stmt::ForRange(format!("_{}", counting.var), counting.start.transform(), counting.end.transform(), step, self.stmt(block).wrap())
+    }
+
+    /// Lowers a class into a `createHashMapObject` declaration.
+    ///
+    /// Field initializers live in a non-virtual `__init_<Class>` method that chains to the base one, and a
+    /// virtual `__init` dispatches to the most derived of them. `#create` calls `__init` before the
+    /// constructor body, so a class without a constructor can inherit its base `#create` through `#base`
+    /// and still get its own fields initialized.
+    fn class_entries(&self, name: &str, base: &Option<String>, members: &[dgen_ast::Stmt]) -> Vec<(String, sqf_ast::Expr)> {
+        let mut entries = vec![("#type".to_string(), sqf_ast::Expr::String(name.to_string()))];
+        let mut init    = Vec::new();
+
+        if let Some(base) = base {
+            entries.push(("#base".to_string(), sqf_ast::Expr::Identifier(format!("_{}", base))));
+            init.push(self_call(&format!("__init_{}", base)));
+        }
+
+        for member in members {
+            if let dgen_ast::Stmt::VarDecl(typename, field, value) = member.unattributed() {
+                if let Some(value) = value.transform().or_else(|| default_value(typename)) {
+                    let pair = sqf_ast::Expr::Array(vec![sqf_ast::Expr::String(field.clone()), value]);
+                    init.push(sqf_ast::Stmt::Expr(sqf_ast::Expr::BinaryCmd(
+                        "set".to_string(),
+                        sqf_ast::Expr::Identifier("_self".to_string()).wrap(),
+                        pair.wrap()
+                    )));
+                }
+            }
+        }
+
+        entries.push((format!("__init_{}", name), sqf_ast::Expr::Code(Vec::new(), sqf_ast::Stmt::Block(init).wrap())));
+        entries.push(("__init".to_string(), sqf_ast::Expr::Code(Vec::new(), sqf_ast::Stmt::Block(vec![self_call(&format!("__init_{}", name))]).wrap())));
+
+        let mut has_ctor = false;
+
+        for member in members {
+            if let dgen_ast::Stmt::FuncDef(_, method, _, params, body) = member.unattributed() {
+                let (key, body) = if method == name {
+                    has_ctor = true;
+                    ("#create".to_string(), block_with_prologue(self_call("__init"), self.stmt(body)))
+                } else if method.starts_with('~') {
+                    ("#delete".to_string(), self.stmt(body))
+                } else {
+                    (method.clone(), self.stmt(body))
+                };
+
+                let (params, body) = function_code(params, body);
+                entries.push((key, sqf_ast::Expr::Code(params, body.wrap())));
+            }
+        }
+
+        if !has_ctor && base.is_none() {
+            entries.push(("#create".to_string(), sqf_ast::Expr::Code(Vec::new(), sqf_ast::Stmt::Block(vec![self_call("__init")]).wrap())));
+        }
+
+        entries
+    }
 }
 
 impl Transform<sqf_ast::Expr> for dgen_ast::Expr {
@@ -82,7 +186,11 @@ impl Transform<sqf_ast::Expr> for dgen_ast::Expr {
             dgen_ast::Expr::Number(n)                       => sqf_ast::Expr::Number(*n),
             dgen_ast::Expr::Bool(b)                         => sqf_ast::Expr::Bool(*b),
             dgen_ast::Expr::String(s)                       => sqf_ast::Expr::String(s.clone()),
-            dgen_ast::Expr::Identifier(id)                  => sqf_ast::Expr::Identifier(format!("_{}", id)),
+            dgen_ast::Expr::Null(typename)                  => null_value(typename.as_ref()),
+            dgen_ast::Expr::Identifier(id)                  => match prelude::find(id) {
+                                                                    Some(builtin)   => sqf_ast::Expr::Identifier(builtin.command.to_string()),
+                                                                    None            => sqf_ast::Expr::Identifier(format!("_{}", id)),
+                                                                },
             dgen_ast::Expr::UnaryOp(op, expr, is_postfix)   => {
                 match op {
                     Operator::Inc => sqf_ast::Expr::BinaryOp(Operator::Add, expr.transform(), sqf_ast::Expr::Number(1.0).wrap()),
@@ -90,10 +198,31 @@ impl Transform<sqf_ast::Expr> for dgen_ast::Expr {
                     _ => sqf_ast::Expr::UnaryOp(op.clone(), expr.transform(), *is_postfix)
                 }
             },
+            dgen_ast::Expr::BinaryOp(op, value, null) if matches!(**null, dgen_ast::Expr::Null(_)) => null_check(op, value.transform(), null),
+            dgen_ast::Expr::BinaryOp(op @ (Operator::And | Operator::Or), left, right) if relies_on(left, *op == Operator::And, right) => {
+                sqf_ast::Expr::BinaryOp(op.clone(), left.transform(), sqf_ast::Expr::Inline(right.transform()).wrap())
+            },
             dgen_ast::Expr::BinaryOp(op, left, right)       => sqf_ast::Expr::BinaryOp(op.clone(), left.transform(), right.transform()),
-            dgen_ast::Expr::FuncCall(name, args)            => sqf_ast::Expr::FuncCall(format!("_{}", name), args.transform()),
+            dgen_ast::Expr::FuncCall(name, args) if args.iter().any(|arg| matches!(arg, dgen_ast::Expr::Spread(_))) => match prelude::find(name) {
+                                                                    Some(builtin)   => sqf_ast::Expr::UnaryCmd(builtin.command.to_string(), spliced_args(args).wrap()),
+                                                                    None            => sqf_ast::Expr::BinaryCmd("call".to_string(), spliced_args(args).wrap(), sqf_ast::Expr::Identifier(format!("_{}", name)).wrap()),
+                                                                },
+            dgen_ast::Expr::FuncCall(name, args)            => match prelude::find(name) {
+                                                                    Some(builtin)   => builtin_call(builtin, args.transform()),
+                                                                    None            => sqf_ast::Expr::FuncCall(format!("_{}", name), args.transform()),
+                                                                },
             dgen_ast::Expr::Array(v)                        => sqf_ast::Expr::Array(v.transform()),
-            dgen_ast::Expr::ArrayAccess(array, index)       => sqf_ast::Expr::ArrayAccess(format!("_{}", array), index.transform())
+            dgen_ast::Expr::ArrayAccess(array, index)       => sqf_ast::Expr::ArrayAccess(format!("_{}", array), index.transform()),
+            dgen_ast::Expr::New(class, args)                => sqf_ast::Expr::UnaryCmd(
+                                                                    "createHashMapObject".to_string(),
+                                                                    sqf_ast::Expr::Array(vec![sqf_ast::Expr::Identifier(format!("_{}", class)), sqf_ast::Expr::Array(args.transform())]).wrap()
+                                                                ),
+            dgen_ast::Expr::Member(target, field)           => sqf_ast::Expr::BinaryCmd("get".to_string(), target.transform(), sqf_ast::Expr::String(field.clone()).wrap()),
+            dgen_ast::Expr::MethodCall(target, method, args) => method_call(target.transform(), method, spliced_args(args)),
+            dgen_ast::Expr::Cast(value, _)                  => value.as_ref().transform(), // converting casts were turned into builtin calls by the analyzer
+            dgen_ast::Expr::NamedArg(_, value)              => value.as_ref().transform(), // the analyzer puts named arguments in parameter order
+            dgen_ast::Expr::Spread(value)                   => value.as_ref().transform(), // calls splice it into their arguments
+            dgen_ast::Expr::Is(value, typename)             => sqf_ast::Expr::BinaryCmd("isEqualType".to_string(), value.transform(), type_sample(typename).wrap()),
         }
     }
 }
@@ -107,7 +236,212 @@ impl Transform<sqf_ast::Type> for dgen_ast::Type {
             dgen_ast::Type::String      => sqf_ast::Type::String,
             dgen_ast::Type::Array(t)    => sqf_ast::Type::Array(t.transform().wrap()),
             dgen_ast::Type::Object      => sqf_ast::Type::Object,
+            dgen_ast::Type::Group       => sqf_ast::Type::Group,
+            dgen_ast::Type::Control     => sqf_ast::Type::Control,
+            dgen_ast::Type::Null        => sqf_ast::Type::Nothing,
+            dgen_ast::Type::Optional(t) => t.transform(),
             dgen_ast::Type::Void        => sqf_ast::Type::Void,
+            dgen_ast::Type::Auto        => sqf_ast::Type::Any,
+            dgen_ast::Type::Class(_)    => sqf_ast::Type::HashMap,
+            dgen_ast::Type::Param(_)    => sqf_ast::Type::Any, // type parameters are erased
+            dgen_ast::Type::Func(..)    => sqf_ast::Type::Code,
+            dgen_ast::Type::Variadic(t) => sqf_ast::Type::Array(t.transform().wrap()),
+            dgen_ast::Type::Union(_)    => sqf_ast::Type::Any,
+            dgen_ast::Type::Unknown     => sqf_ast::Type::Any,
+        }
+    }
+}
+
+fn builtin_call(builtin: &prelude::Builtin, args: Vec<sqf_ast::Expr>) -> sqf_ast::Expr {
+    let command     = builtin.command.to_string();
+    let mut args    = args.into_iter();
+
+    if builtin.is_variadic() {
+        return sqf_ast::Expr::UnaryCmd(command, sqf_ast::Expr::Array(args.collect()).wrap());
+    }
+
+    match builtin.kind {
+        prelude::Command::Nular     => sqf_ast::Expr::Identifier(command),
+        prelude::Command::Unary     => sqf_ast::Expr::UnaryCmd(command, args.next().unwrap().wrap()),
+        prelude::Command::Binary    => sqf_ast::Expr::BinaryCmd(command, args.next().unwrap().wrap(), args.next().unwrap().wrap()),
+    }
+}
+
+// The arguments of a call as an array, with the rest parameters passed on spliced in: `[_a] + _rest`
+fn spliced_args(args: &[dgen_ast::Expr]) -> sqf_ast::Expr {
+    let mut parts       = Vec::new();
+    let mut elements    = Vec::new();
+
+    for arg in args {
+        match arg {
+            dgen_ast::Expr::Spread(rest) => {
+                if !elements.is_empty() || parts.is_empty() {
+                    parts.push(sqf_ast::Expr::Array(std::mem::take(&mut elements)));
+                }
+                parts.push(rest.as_ref().transform());
+            },
+            arg => elements.push(arg.transform()),
+        }
+    }
+    if !elements.is_empty() || parts.is_empty() {
+        parts.push(sqf_ast::Expr::Array(elements));
+    }
+
+    parts.into_iter().reduce(|left, right| sqf_ast::Expr::BinaryOp(Operator::Add, left.wrap(), right.wrap())).unwrap()
+}
+
+fn method_call(target: Box<sqf_ast::Expr>, method: &str, args: sqf_ast::Expr) -> sqf_ast::Expr {
+    let call = sqf_ast::Expr::Array(vec![sqf_ast::Expr::String(method.to_string()), args]);
+    sqf_ast::Expr::BinaryCmd("call".to_string(), target, call.wrap())
+}
+
+fn self_call(method: &str) -> sqf_ast::Stmt {
+    sqf_ast::Stmt::Expr(method_call(sqf_ast::Expr::Identifier("_self".to_string()).wrap(), method, sqf_ast::Expr::Array(Vec::new())))
+}
+
+// Value a field holds before its class constructor runs, if SQF has a sensible one for the type
+fn default_value(typename: &dgen_ast::Type) -> Option<sqf_ast::Expr> {
+    match typename {
+        dgen_ast::Type::Number      => Some(sqf_ast::Expr::Number(0.0)),
+        dgen_ast::Type::String      => Some(sqf_ast::Expr::String(String::new())),
+        dgen_ast::Type::Boolean     => Some(sqf_ast::Expr::Bool(false)),
+        dgen_ast::Type::Object      => Some(sqf_ast::Expr::Identifier("objNull".to_string())),
+        dgen_ast::Type::Array(_)    => Some(sqf_ast::Expr::Array(Vec::new())),
+        dgen_ast::Type::Optional(_) => null_default(typename),
+        _                           => None,
+    }
+}
+
+// Types with a dedicated null value; the rest are null when they are `nil`
+fn null_command(typename: Option<&dgen_ast::Type>) -> Option<&'static str> {
+    match typename {
+        Some(dgen_ast::Type::Object)    => Some("objNull"),
+        Some(dgen_ast::Type::Group)     => Some("grpNull"),
+        Some(dgen_ast::Type::Control)   => Some("controlNull"),
+        _                               => None,
+    }
+}
+
+// A value of the type for `isEqualType` to compare against; the analyzer only lets testable types through
+fn type_sample(typename: &dgen_ast::Type) -> sqf_ast::Expr {
+    match typename {
+        dgen_ast::Type::Number      => sqf_ast::Expr::Number(0.0),
+        dgen_ast::Type::String      => sqf_ast::Expr::String(String::new()),
+        dgen_ast::Type::Boolean     => sqf_ast::Expr::Bool(true),
+        dgen_ast::Type::Array(_)    => sqf_ast::Expr::Array(Vec::new()),
+        dgen_ast::Type::Class(_)    => sqf_ast::Expr::Identifier("createHashMap".to_string()),
+        dgen_ast::Type::Func(..)    => sqf_ast::Expr::Identifier("{}".to_string()),
+        other                       => null_value(Some(other)),
+    }
+}
+
+fn null_value(typename: Option<&dgen_ast::Type>) -> sqf_ast::Expr {
+    sqf_ast::Expr::Identifier(null_command(typename).unwrap_or("nil").to_string())
+}
+
+// An optional declared without a value starts out null; `private` alone already leaves it `nil`
+fn null_default(typename: &dgen_ast::Type) -> Option<sqf_ast::Expr> {
+    match typename {
+        dgen_ast::Type::Optional(inner) => null_command(Some(inner)).map(|_| null_value(Some(inner))),
+        _                               => None,
+    }
+}
+
+// `x == null` becomes `isNull x` for types with a null value and `isNil {x}` for the rest
+fn null_check(op: &Operator, value: Box<sqf_ast::Expr>, null: &dgen_ast::Expr) -> sqf_ast::Expr {
+    let typename = match null {
+        dgen_ast::Expr::Null(typename)  => typename.as_ref(),
+        _                               => None,
+    };
+
+    let check = match null_command(typename) {
+        Some(_) => sqf_ast::Expr::UnaryCmd("isNull".to_string(), value),
+        None    => sqf_ast::Expr::UnaryCmd("isNil".to_string(), sqf_ast::Expr::Inline(value).wrap()),
+    };
+
+    match op {
+        Operator::Neq   => sqf_ast::Expr::UnaryOp(Operator::Not, check.wrap(), false),
+        _               => check,
+    }
+}
+
+// Whether `right` reads a variable `left` checks when it is `outcome`. SQF evaluates both sides of
+// `&&` and `||` unless the right one is code, which it only runs once the left one has decided.
+fn relies_on(left: &dgen_ast::Expr, outcome: bool, right: &dgen_ast::Expr) -> bool {
+    let checked = narrowing::checked_names(left, outcome);
+    let mut reads = false;
+
+    right.walk(&mut |expr| if let dgen_ast::Expr::Identifier(name) | dgen_ast::Expr::ArrayAccess(name, _) = expr {
+        reads |= checked.contains(name);
+    });
+
+    reads
+}
+
+// The variable `++` or `--` steps and which of them it is
+fn stepped_variable(expr: &dgen_ast::Expr) -> Option<(&String, &Operator)> {
+    match expr {
+        dgen_ast::Expr::UnaryOp(op @ (Operator::Inc | Operator::Dec), operand, _) => match &**operand {
+            dgen_ast::Expr::Identifier(name)    => Some((name, op)),
+            _                                   => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parameters and body of a function in SQF.
+///
+/// `params` can't collect the rest of the arguments, so a variadic parameter is sliced off `_this` instead.
+fn function_code(params: &[(dgen_ast::Type, String, Option<dgen_ast::Expr>)], body: sqf_ast::Stmt) -> (Vec<(sqf_ast::Type, String, Option<sqf_ast::Expr>)>, sqf_ast::Stmt) {
+    match params.split_last() {
+        Some(((dgen_ast::Type::Variadic(_), name, _), fixed)) => {
+            let this    = sqf_ast::Expr::Identifier("_this".to_string());
+            let range   = sqf_ast::Expr::Array(vec![
+                sqf_ast::Expr::Number(fixed.len() as f64),
+                sqf_ast::Expr::UnaryCmd("count".to_string(), this.clone().wrap()),
+            ]);
+            let rest    = sqf_ast::Stmt::VarDecl(format!("_{}", name), Some(sqf_ast::Expr::BinaryCmd("select".to_string(), this.wrap(), range.wrap())));
+
+            (fixed.to_vec().transform(), block_with_prologue(rest, body))
         }
+        _ => (params.to_vec().transform(), body),
+    }
+}
+
+fn block_with_prologue(prologue: sqf_ast::Stmt, body: sqf_ast::Stmt) -> sqf_ast::Stmt {
+    match body {
+        sqf_ast::Stmt::Block(stmts) => sqf_ast::Stmt::Block(std::iter::once(prologue).chain(stmts).collect()),
+        body                        => sqf_ast::Stmt::Block(vec![prologue, body]),
     }
 }
+
+/// Name of a function overload in SQF, which has no overloading: its parameter types are spelled out after it.
+///
+/// The leading underscore keeps it apart from anything a dGen identifier could be called.
+pub fn mangle(name: &str, params: &[dgen_ast::Type]) -> String {
+    let suffixes: Vec<String> = params.iter().map(type_suffix).collect();
+    format!("_{}_{}", name, suffixes.join("_"))
+}
+
+fn type_suffix(typename: &dgen_ast::Type) -> String {
+    match typename {
+        dgen_ast::Type::Array(element)      => format!("{}_array", type_suffix(element)),
+        dgen_ast::Type::Optional(inner)     => format!("{}_opt", type_suffix(inner)),
+        dgen_ast::Type::Class(name)         => name.clone(),
+        dgen_ast::Type::Param(name)         => name.clone(),
+        dgen_ast::Type::Func(..)            => "func".to_string(),
+        dgen_ast::Type::Variadic(element)   => format!("{}_rest", type_suffix(element)),
+        dgen_ast::Type::Union(members)      => members.iter().map(type_suffix).collect::<Vec<_>>().join("_or_"),
+        other                               => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+/// Moves the top-level function definitions to the start of the program and drops the prototypes.
+///
+/// Functions may be called before their definition, but in SQF they are plain variables that only
+/// exist once the assignment has run. With the definitions first, prototypes have nothing left to say.
+fn hoist_functions(stmts: &[dgen_ast::Stmt]) -> Vec<dgen_ast::Stmt> {
+    let code = stmts.iter().filter(|stmt| !matches!(stmt.unattributed(), dgen_ast::Stmt::FuncDecl(..))).cloned();
+    let (functions, rest): (Vec<_>, Vec<_>) = code.partition(|stmt| matches!(stmt.unattributed(), dgen_ast::Stmt::FuncDef(..)));
+    functions.into_iter().chain(rest).collect()
+}
@@ -1,6 +1,5 @@
-use clap::builder::Str;
-
 use crate::generic::*;
+use crate::boxable::Boxable;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
@@ -9,8 +8,22 @@ pub enum Type {
     String,
     Boolean,
     Void,
+    Auto,
     Object,
+    Group,
+    Control,
+    Null,
+    Optional(Box<Type>),
     Array(Box<Type>),
+    Class(String),
+    Param(String),
+    Func(Vec<Type>, Box<Type>),
+    // The last parameter of a function taking any number of arguments of the inner type
+    Variadic(Box<Type>),
+    // A value of any of the member types, told apart at run time with `is`
+    Union(Vec<Type>),
+    // Type of an expression that failed to check, accepted anywhere so the error isn't repeated
+    Unknown,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -18,12 +31,23 @@ pub enum Expr {
     Number(f64),
     Bool(bool),
     String(String),
+    Null(Option<Type>),
     Array(Vec<Expr>),
     ArrayAccess(String, Box<Expr>),
     Identifier(String),
     UnaryOp(Operator, Box<Expr>, bool),
     BinaryOp(Operator, Box<Expr>, Box<Expr>),
     FuncCall(String, Vec<Expr>),
+    New(String, Vec<Expr>),
+    Member(Box<Expr>, String),
+    MethodCall(Box<Expr>, String, Vec<Expr>),
+    Cast(Box<Expr>, Type),
+    // `value is type`, a run-time type test
+    Is(Box<Expr>, Type),
+    // `name: value` in a call's argument list
+    NamedArg(String, Box<Expr>),
+    // A rest parameter passed on to a variadic parameter, standing for the arguments it collected
+    Spread(Box<Expr>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -32,14 +56,131 @@ pub enum Stmt {
     VarDecl(Type, String, Option<Expr>),
     ArrayDecl(Type, String, Option<Expr>),
     FuncDecl(Type, String, Vec<Type>),
-    FuncDef(Type, String, Vec<(Type, String)>, Box<Stmt>),
+    FuncDef(Type, String, Vec<String>, Vec<(Type, String, Option<Expr>)>, Box<Stmt>),
     Assign(String, Expr),
+    MemberAssign(Expr, String, Expr),
+    ClassDef(String, Option<String>, Vec<Stmt>),
+    Attributed(Vec<(String, Vec<String>)>, Box<Stmt>),
     Block(Vec<Stmt>),
     Program(Vec<Stmt>),
-    Return(Expr),
+    Return(Option<Expr>),
     Break,
     Continue,
     If(Expr, Box<Stmt>, Option<Box<Stmt>>),
     For(Box<Stmt>, Expr, Box<Stmt>, Box<Stmt>),
     While(Expr, Box<Stmt>),
+}
+
+impl Expr {
+    /// The expressions directly inside this one, in evaluation order.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Array(elements)                   => elements.iter().collect(),
+            Expr::ArrayAccess(_, index)             => vec![index],
+            Expr::UnaryOp(_, operand, _)            => vec![operand],
+            Expr::BinaryOp(_, left, right)          => vec![left, right],
+            Expr::FuncCall(_, args) | Expr::New(_, args) => args.iter().collect(),
+            Expr::Member(target, _)                 => vec![target],
+            Expr::MethodCall(target, _, args)       => std::iter::once(&**target).chain(args).collect(),
+            Expr::Cast(value, _) | Expr::Is(value, _) | Expr::NamedArg(_, value) | Expr::Spread(value) => vec![value],
+            Expr::Number(_) | Expr::Bool(_) | Expr::String(_) | Expr::Null(_) | Expr::Identifier(_) => Vec::new(),
+        }
+    }
+
+    /// Calls `f` on this expression and every one nested in it, outermost first.
+    pub fn walk(&self, f: &mut dyn FnMut(&Expr)) {
+        f(self);
+        for child in self.children() {
+            child.walk(f);
+        }
+    }
+
+    /// The expression with `f` applied to each of the expressions directly inside it.
+    pub fn map_children(self, f: &mut dyn FnMut(Expr) -> Expr) -> Expr {
+        use Expr::*;
+
+        match self {
+            Array(elements)                     => Array(elements.into_iter().map(&mut *f).collect()),
+            ArrayAccess(name, index)            => ArrayAccess(name, f(*index).wrap()),
+            UnaryOp(op, operand, is_postfix)    => UnaryOp(op, f(*operand).wrap(), is_postfix),
+            BinaryOp(op, left, right)           => BinaryOp(op, f(*left).wrap(), f(*right).wrap()),
+            FuncCall(name, args)                => FuncCall(name, args.into_iter().map(&mut *f).collect()),
+            New(class, args)                    => New(class, args.into_iter().map(&mut *f).collect()),
+            Member(target, field)               => Member(f(*target).wrap(), field),
+            MethodCall(target, method, args)    => MethodCall(f(*target).wrap(), method, args.into_iter().map(&mut *f).collect()),
+            Cast(value, typename)               => Cast(f(*value).wrap(), typename),
+            Is(value, typename)                 => Is(f(*value).wrap(), typename),
+            NamedArg(name, value)               => NamedArg(name, f(*value).wrap()),
+            Spread(value)                       => Spread(f(*value).wrap()),
+            leaf                                => leaf,
+        }
+    }
+}
+
+impl Stmt {
+    /// The expressions this statement evaluates itself, leaving out those of the statements inside it.
+    pub fn exprs(&self) -> Vec<&Expr> {
+        match self {
+            Stmt::Expr(expr) | Stmt::Assign(_, expr)            => vec![expr],
+            Stmt::VarDecl(_, _, value) | Stmt::ArrayDecl(_, _, value) | Stmt::Return(value) => value.iter().collect(),
+            Stmt::MemberAssign(target, _, value)                => vec![target, value],
+            Stmt::If(cond, _, _) | Stmt::For(_, cond, _, _) | Stmt::While(cond, _) => vec![cond],
+            Stmt::FuncDef(_, _, _, params, _)                   => params.iter().filter_map(|(_, _, default)| default.as_ref()).collect(),
+            _                                                   => Vec::new(),
+        }
+    }
+
+    /// The statements directly inside this one.
+    pub fn children(&self) -> Vec<&Stmt> {
+        match self {
+            Stmt::Block(stmts) | Stmt::Program(stmts) | Stmt::ClassDef(_, _, stmts) => stmts.iter().collect(),
+            Stmt::FuncDef(_, _, _, _, body) | Stmt::Attributed(_, body) | Stmt::While(_, body) => vec![body],
+            Stmt::If(_, if_block, else_block)   => std::iter::once(&**if_block).chain(else_block.as_deref()).collect(),
+            Stmt::For(init, _, step, block)     => vec![init, step, block],
+            _                                   => Vec::new(),
+        }
+    }
+
+    /// Calls `f` on this statement and every one nested in it, outermost first.
+    pub fn walk(&self, f: &mut dyn FnMut(&Stmt)) {
+        f(self);
+        for child in self.children() {
+            child.walk(f);
+        }
+    }
+
+    /// Like `walk`, without going into the functions and classes defined inside the statement.
+    pub fn walk_local(&self, f: &mut dyn FnMut(&Stmt)) {
+        f(self);
+        for child in self.children() {
+            if !matches!(child.unattributed(), Stmt::FuncDef(..) | Stmt::ClassDef(..)) {
+                child.walk_local(f);
+            }
+        }
+    }
+
+    /// Calls `f` on every expression this statement and the ones inside it evaluate, nested ones included.
+    pub fn walk_exprs(&self, f: &mut dyn FnMut(&Expr)) {
+        self.walk(&mut |stmt| {
+            for expr in stmt.exprs() {
+                expr.walk(f);
+            }
+        });
+    }
+
+    /// Whether the statement carries the attribute, with or without arguments.
+    pub fn has_attribute(&self, attribute: &str) -> bool {
+        match self {
+            Stmt::Attributed(attributes, stmt)  => attributes.iter().any(|(name, _)| name == attribute) || stmt.has_attribute(attribute),
+            _                                   => false,
+        }
+    }
+
+    /// The statement with any attributes stripped off.
+    pub fn unattributed(&self) -> &Stmt {
+        match self {
+            Stmt::Attributed(_, stmt)   => stmt.unattributed(),
+            stmt                        => stmt,
+        }
+    }
 }
\ No newline at end of file
@@ -19,9 +19,9 @@ pub enum Operator {
     Gte,
 }
 
-impl Operator {
-    pub fn to_string(&self) -> String {
-        match self {
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
             Operator::Add => "+",
             Operator::Sub => "-",
             Operator::Mul => "*",
@@ -39,6 +39,6 @@ impl Operator {
             Operator::Gt  => ">",
             Operator::Lte => "<=",
             Operator::Gte => ">=",
-        }.to_string()
+        })
     }
 }
\ No newline at end of file
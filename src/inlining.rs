@@ -0,0 +1,445 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::generic::*;
+use crate::dgen_ast::*;
+use crate::boxable::Boxable;
+use crate::control_flow;
+use crate::dead_code::read_names;
+use crate::optimizer::has_side_effects;
+use crate::propagation::{calls_user_code, stepped_names};
+
+// Attributes that ask for a function to always or never be inlined
+const INLINE: &str      = "inline";
+const NOINLINE: &str    = "noinline";
+
+/// Functions whose body has at most this many statements and expressions are inlined without being asked to.
+pub const INLINE_SIZE: usize = 16;
+
+/// Bodies this small take no more room than the call they replace.
+pub const CALL_SIZE: usize = 6;
+
+// A function whose calls can be replaced by its body
+#[derive(Clone)]
+struct Inlinee {
+    return_type: Type,
+    params: Vec<(Type, String)>,
+    body: Vec<Stmt>,
+    // Variables the body declares, renamed at every call site along with the parameters
+    locals: Vec<String>,
+    // Everything else the body refers to, which must mean the same at the call site
+    free: HashSet<String>,
+}
+
+// Where the value of an inlined call goes
+enum Sink {
+    Discard,
+    Assign(String),
+}
+
+/// Replaces calls to top-level functions with a body of at most `max_size` statements and expressions,
+/// and to those marked `#[inline]`, with their body.
+/// Calls whose arguments can stand in for the parameters of a function that only returns an
+/// expression become that expression; other calls made as a statement, or whose value is assigned,
+/// declared or returned, become statements declaring the parameters under fresh names followed by
+/// the body, its `return`s turned into assignments. Recursive functions and those marked
+/// `#[noinline]` are left alone.
+pub fn inline(program: Stmt, max_size: usize) -> Stmt {
+    let Stmt::Program(mut stmts) = program else { return program };
+
+    // Bodies that got calls inlined into them can be inlined in turn, which ends since none is recursive
+    loop {
+        let mut inliner = Inliner { functions: inlinable_functions(&stmts, max_size), taken: all_names(&stmts) };
+
+        if inliner.functions.is_empty() {
+            return Stmt::Program(stmts);
+        }
+
+        let next = inliner.program(stmts.clone());
+
+        if next == stmts {
+            return Stmt::Program(stmts);
+        }
+        stmts = next;
+    }
+}
+
+struct Inliner {
+    functions: HashMap<String, Inlinee>,
+    // Names in use anywhere in the program, so the renamed parameters and locals can't clash with them
+    taken: HashSet<String>,
+}
+
+impl Inliner {
+    fn program(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        // Variables of the top-level code that aren't globals
+        let mut locals = HashSet::new();
+        for stmt in stmts.iter().filter(|stmt| !matches!(stmt.unattributed(), Stmt::FuncDef(..) | Stmt::ClassDef(..))) {
+            for child in stmt.children() {
+                locals.extend(declared_names(child));
+            }
+        }
+
+        self.block(stmts, &locals)
+    }
+
+    fn block(&mut self, stmts: Vec<Stmt>, locals: &HashSet<String>) -> Vec<Stmt> {
+        stmts.into_iter().flat_map(|stmt| self.stmt(stmt, locals)).collect()
+    }
+
+    // The statement with the calls in it inlined, or the statements a call it makes was inlined into
+    fn stmt(&mut self, stmt: Stmt, locals: &HashSet<String>) -> Vec<Stmt> {
+        match stmt {
+            Stmt::Expr(Expr::FuncCall(name, args)) => match self.expand(&name, &args, Sink::Discard, locals) {
+                Some(stmts) => stmts,
+                None        => vec![Stmt::Expr(self.expr(Expr::FuncCall(name, args), locals))],
+            },
+            Stmt::VarDecl(typename, name, Some(Expr::FuncCall(function, args))) => match self.expand(&function, &args, Sink::Assign(name.clone()), locals) {
+                Some(stmts) => std::iter::once(Stmt::VarDecl(typename, name, None)).chain(stmts).collect(),
+                None        => vec![Stmt::VarDecl(typename, name, Some(self.expr(Expr::FuncCall(function, args), locals)))],
+            },
+            Stmt::Assign(name, Expr::FuncCall(function, args)) => match self.expand(&function, &args, Sink::Assign(name.clone()), locals) {
+                Some(stmts) => stmts,
+                None        => vec![Stmt::Assign(name, self.expr(Expr::FuncCall(function, args), locals))],
+            },
+            Stmt::Return(Some(Expr::FuncCall(function, args))) => {
+                let result = self.functions.get(&function).filter(|inlinee| inlinee.return_type != Type::Void).map(|inlinee| inlinee.return_type.clone());
+
+                match result.map(|return_type| (return_type, self.fresh("result"))) {
+                    Some((return_type, name)) => match self.expand(&function, &args, Sink::Assign(name.clone()), locals) {
+                        Some(stmts) => std::iter::once(Stmt::VarDecl(return_type, name.clone(), None)).chain(stmts).chain([Stmt::Return(Some(Expr::Identifier(name)))]).collect(),
+                        None        => vec![Stmt::Return(Some(self.expr(Expr::FuncCall(function, args), locals)))],
+                    },
+                    None => vec![Stmt::Return(Some(self.expr(Expr::FuncCall(function, args), locals)))],
+                }
+            },
+            other => vec![self.nested(other, locals)],
+        }
+    }
+
+    // A statement that isn't a call itself, with the calls in its expressions and statements inlined
+    fn nested(&mut self, stmt: Stmt, locals: &HashSet<String>) -> Stmt {
+        use Stmt::*;
+
+        match stmt {
+            Expr(expr)                          => Expr(self.expr(expr, locals)),
+            VarDecl(typename, name, value)      => VarDecl(typename, name, value.map(|value| self.expr(value, locals))),
+            ArrayDecl(typename, name, value)    => ArrayDecl(typename, name, value.map(|value| self.expr(value, locals))),
+            Assign(name, value)                 => Assign(name, self.expr(value, locals)),
+            MemberAssign(target, field, value)  => MemberAssign(self.expr(target, locals), field, self.expr(value, locals)),
+            Return(value)                       => Return(value.map(|value| self.expr(value, locals))),
+            Block(stmts)                        => Block(self.block(stmts, locals)),
+            If(cond, if_block, else_block)      => If(self.expr(cond, locals), self.nested(*if_block, locals).wrap(), else_block.map(|else_block| self.nested(*else_block, locals).wrap())),
+            While(cond, block)                  => While(self.expr(cond, locals), self.nested(*block, locals).wrap()),
+            For(init, cond, step, block)        => For(self.nested(*init, locals).wrap(), self.expr(cond, locals), self.nested(*step, locals).wrap(), self.nested(*block, locals).wrap()),
+            FuncDef(return_type, name, type_params, params, body) => {
+                let mut locals: HashSet<String> = params.iter().map(|(_, name, _)| name.clone()).collect();
+                locals.extend(declared_names(&body));
+
+                FuncDef(return_type, name, type_params, params, self.nested(*body, &locals).wrap())
+            },
+            ClassDef(name, base, members)       => ClassDef(name, base, members.into_iter().map(|member| self.nested(member, locals)).collect()),
+            Attributed(attributes, stmt)        => Attributed(attributes, self.nested(*stmt, locals).wrap()),
+            other                               => other,
+        }
+    }
+
+    // The expression with the calls to functions that only return an expression replaced by it, where the arguments allow
+    fn expr(&mut self, expr: Expr, locals: &HashSet<String>) -> Expr {
+        let expr = expr.map_children(&mut |child| self.expr(child, locals));
+
+        let Expr::FuncCall(name, args) = &expr else { return expr };
+        let Some(inlinee) = self.functions.get(name).filter(|inlinee| inlinee.fits(args, locals)) else { return expr };
+        let [Stmt::Return(Some(value))] = inlinee.body.as_slice() else { return expr };
+
+        let mut values = HashMap::new();
+
+        for ((_, param), arg) in inlinee.params.iter().zip(args) {
+            // An argument is evaluated once, so one that does more than read a variable can't be put in twice
+            let trivial = matches!(arg, Expr::Number(_) | Expr::Bool(_) | Expr::String(_) | Expr::Null(_) | Expr::Identifier(_));
+
+            if !trivial && (has_side_effects(arg) || uses(value, param) > 1) {
+                return expr;
+            }
+            // A substituted argument is read where the body uses it, after whatever the body did first;
+            // calls made as a statement are expanded instead, with the argument declared before the body
+            if changes_reads(value, arg) {
+                return expr;
+            }
+            values.insert(param.clone(), arg.clone());
+        }
+
+        bind(value.clone(), &values).unwrap_or(expr)
+    }
+
+    // The statements a call made as a statement is replaced by
+    fn expand(&mut self, name: &str, args: &[Expr], sink: Sink, locals: &HashSet<String>) -> Option<Vec<Stmt>> {
+        let inlinee = self.functions.get(name).filter(|inlinee| inlinee.fits(args, locals))?.clone();
+        let args: Vec<Expr> = args.iter().map(|arg| self.expr(arg.clone(), locals)).collect();
+
+        let mut names = HashMap::new();
+        for name in inlinee.params.iter().map(|(_, name)| name).chain(&inlinee.locals) {
+            let fresh = self.fresh(name);
+            names.insert(name.clone(), fresh);
+        }
+
+        let mut stmts: Vec<Stmt> = inlinee.params.iter().zip(args).map(|((typename, param), arg)| Stmt::VarDecl(typename.clone(), names[param].clone(), Some(arg))).collect();
+        let body = inlinee.body.into_iter().map(|stmt| rename(stmt, &names)).collect();
+
+        stmts.extend(lower_returns(body, &sink)?);
+        Some(stmts)
+    }
+
+    fn fresh(&mut self, name: &str) -> String {
+        let fresh = (1..).map(|n| format!("{}_{}", name, n)).find(|fresh| !self.taken.contains(fresh)).unwrap();
+        self.taken.insert(fresh.clone());
+        fresh
+    }
+}
+
+impl Inlinee {
+    // Whether the call passes every parameter by position, and nothing the body refers to is hidden by a local of the caller
+    fn fits(&self, args: &[Expr], locals: &HashSet<String>) -> bool {
+        args.len() == self.params.len()
+            && !args.iter().any(|arg| matches!(arg, Expr::NamedArg(..)))
+            && self.free.is_disjoint(locals)
+    }
+}
+
+// Top-level functions that are small enough or marked `#[inline]`, don't call themselves and have a body that can be inlined
+fn inlinable_functions(stmts: &[Stmt], max_size: usize) -> HashMap<String, Inlinee> {
+    let functions: HashMap<&String, &Stmt> = stmts.iter().filter_map(|stmt| match stmt.unattributed() {
+        Stmt::FuncDef(_, name, ..) => Some((name, stmt)),
+        _ => None,
+    }).collect();
+
+    functions.iter().filter_map(|(name, stmt)| {
+        let Stmt::FuncDef(return_type, _, _, params, body) = stmt.unattributed() else { return None };
+        let Stmt::Block(body) = &**body else { return None };
+
+        let wanted      = stmt.has_attribute(INLINE) || size(body) <= max_size;
+        let nested      = body.iter().any(|stmt| {
+            let mut nested = false;
+            stmt.walk(&mut |stmt| nested |= matches!(stmt, Stmt::FuncDef(..) | Stmt::ClassDef(..)));
+            nested
+        });
+        let variadic    = params.iter().any(|(typename, _, _)| matches!(typename, Type::Variadic(_)));
+
+        if !wanted || nested || variadic || stmt.has_attribute(NOINLINE) || calls(name, name, &functions, &mut HashSet::new()) {
+            return None;
+        }
+
+        let params: Vec<(Type, String)> = params.iter().map(|(typename, name, _)| (typename.clone(), name.clone())).collect();
+        let locals: Vec<String>         = body.iter().flat_map(declared_names).collect();
+        let free                        = body.iter().flat_map(read_names).chain(body.iter().flat_map(assigned_names))
+                                            .filter(|name| !params.iter().any(|(_, param)| param == name) && !locals.contains(name))
+                                            .collect();
+
+        // Returns from inside loops can't be turned into assignments
+        lower_returns(body.clone(), &Sink::Discard)?;
+
+        Some((name.to_string(), Inlinee { return_type: return_type.clone(), params, body: body.clone(), locals, free }))
+    }).collect()
+}
+
+// Whether `function` calls `target`, directly or through other top-level functions
+fn calls(function: &str, target: &str, functions: &HashMap<&String, &Stmt>, visited: &mut HashSet<String>) -> bool {
+    let Some(stmt) = functions.get(&function.to_string()) else { return false };
+
+    if !visited.insert(function.to_string()) {
+        return false;
+    }
+
+    read_names(stmt).iter().filter(|name| functions.contains_key(name)).any(|callee| callee == target || calls(callee, target, functions, visited))
+}
+
+// Whether evaluating `value` may change a variable `arg` reads: it steps one, or calls user code that may assign it
+fn changes_reads(value: &Expr, arg: &Expr) -> bool {
+    let mut reads = HashSet::new();
+
+    arg.walk(&mut |expr| if let Expr::Identifier(name) | Expr::ArrayAccess(name, _) = expr {
+        reads.insert(name.clone());
+    });
+
+    !reads.is_empty() && (calls_user_code(value) || !stepped_names(value).is_disjoint(&reads))
+}
+
+// Number of statements and expressions in the body
+fn size(body: &[Stmt]) -> usize {
+    let mut size = 0;
+
+    for stmt in body {
+        stmt.walk(&mut |_| size += 1);
+        stmt.walk_exprs(&mut |_| size += 1);
+    }
+
+    size
+}
+
+// Variables the statement declares, in it or in the statements inside it
+fn declared_names(stmt: &Stmt) -> Vec<String> {
+    let mut names = Vec::new();
+
+    stmt.walk_local(&mut |stmt| if let Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) = stmt {
+        names.push(name.clone());
+    });
+
+    names
+}
+
+fn assigned_names(stmt: &Stmt) -> Vec<String> {
+    let mut names = Vec::new();
+
+    stmt.walk(&mut |stmt| if let Stmt::Assign(name, _) = stmt {
+        names.push(name.clone());
+    });
+
+    names
+}
+
+/// Every name the statements declare, assign or read.
+pub fn all_names(stmts: &[Stmt]) -> HashSet<String> {
+    let mut names = HashSet::new();
+
+    for stmt in stmts {
+        names.extend(read_names(stmt));
+        names.extend(assigned_names(stmt));
+        stmt.walk(&mut |stmt| match stmt {
+            Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) => { names.insert(name.clone()); },
+            Stmt::FuncDef(_, _, _, params, _) => names.extend(params.iter().map(|(_, name, _)| name.clone())),
+            _ => (),
+        });
+    }
+
+    names
+}
+
+fn uses(expr: &Expr, name: &str) -> usize {
+    let mut uses = 0;
+
+    expr.walk(&mut |expr| if let Expr::Identifier(n) | Expr::ArrayAccess(n, _) | Expr::FuncCall(n, _) = expr {
+        uses += (n == name) as usize;
+    });
+
+    uses
+}
+
+// The expression with the parameters replaced by the arguments; None where a parameter has to stay a variable
+fn bind(expr: Expr, values: &HashMap<String, Expr>) -> Option<Expr> {
+    let mut bound = true;
+
+    let expr = bind_in(expr, values, &mut bound);
+    bound.then_some(expr)
+}
+
+fn bind_in(expr: Expr, values: &HashMap<String, Expr>, bound: &mut bool) -> Expr {
+    match expr {
+        Expr::Identifier(name)                  => values.get(&name).cloned().unwrap_or(Expr::Identifier(name)),
+        Expr::ArrayAccess(name, index)          => match values.get(&name) {
+            Some(Expr::Identifier(array))       => Expr::ArrayAccess(array.clone(), bind_in(*index, values, bound).wrap()),
+            Some(_)                             => { *bound = false; Expr::ArrayAccess(name, index) },
+            None                                => Expr::ArrayAccess(name, bind_in(*index, values, bound).wrap()),
+        },
+        // A parameter holding a function is called through whatever function the argument names
+        Expr::FuncCall(name, args)              => {
+            let args = args.into_iter().map(|arg| bind_in(arg, values, bound)).collect();
+
+            match values.get(&name) {
+                Some(Expr::Identifier(function))    => Expr::FuncCall(function.clone(), args),
+                Some(_)                             => { *bound = false; Expr::FuncCall(name, args) },
+                None                                => Expr::FuncCall(name, args),
+            }
+        },
+        // Stepping a parameter changes the function's copy, not the argument
+        Expr::UnaryOp(Operator::Inc | Operator::Dec, ref operand, _) if matches!(&**operand, Expr::Identifier(name) if values.contains_key(name)) => {
+            *bound = false;
+            expr
+        },
+        other                                   => other.map_children(&mut |child| bind_in(child, values, bound)),
+    }
+}
+
+fn rename_expr(expr: Expr, names: &HashMap<String, String>) -> Expr {
+    let renamed = |name: String| names.get(&name).cloned().unwrap_or(name);
+
+    match expr {
+        Expr::Identifier(name)              => Expr::Identifier(renamed(name)),
+        Expr::ArrayAccess(name, index)      => Expr::ArrayAccess(renamed(name), rename_expr(*index, names).wrap()),
+        Expr::FuncCall(name, args)          => Expr::FuncCall(renamed(name), args.into_iter().map(|arg| rename_expr(arg, names)).collect()),
+        other                               => other.map_children(&mut |child| rename_expr(child, names)),
+    }
+}
+
+// The statement with the variables it refers to renamed
+fn rename(stmt: Stmt, names: &HashMap<String, String>) -> Stmt {
+    let renamed     = |name: String| names.get(&name).cloned().unwrap_or(name);
+    let expr        = |expr: Expr| rename_expr(expr, names);
+    let nested      = |stmt: Box<Stmt>| rename(*stmt, names).wrap();
+
+    match stmt {
+        Stmt::Expr(value)                         => Stmt::Expr(expr(value)),
+        Stmt::VarDecl(typename, name, value)      => Stmt::VarDecl(typename, renamed(name), value.map(expr)),
+        Stmt::ArrayDecl(typename, name, value)    => Stmt::ArrayDecl(typename, renamed(name), value.map(expr)),
+        Stmt::Assign(name, value)                 => Stmt::Assign(renamed(name), expr(value)),
+        Stmt::MemberAssign(target, field, value)  => Stmt::MemberAssign(expr(target), field, expr(value)),
+        Stmt::Return(value)                       => Stmt::Return(value.map(expr)),
+        Stmt::Block(stmts)                        => Stmt::Block(stmts.into_iter().map(|stmt| rename(stmt, names)).collect()),
+        Stmt::If(cond, if_block, else_block)      => Stmt::If(expr(cond), nested(if_block), else_block.map(nested)),
+        Stmt::While(cond, block)                  => Stmt::While(expr(cond), nested(block)),
+        Stmt::For(init, cond, step, block)        => Stmt::For(nested(init), expr(cond), nested(step), nested(block)),
+        Stmt::Attributed(attributes, stmt)        => Stmt::Attributed(attributes, nested(stmt)),
+        other                               => other,
+    }
+}
+
+// The body with each `return` turned into `sink`; an `if` that returns takes in the statements after it, so
+// they only run on the paths that didn't return. None when that would copy them, or a loop returns.
+fn lower_returns(stmts: Vec<Stmt>, sink: &Sink) -> Option<Vec<Stmt>> {
+    let mut lowered = Vec::new();
+    let mut stmts   = stmts.into_iter();
+
+    while let Some(stmt) = stmts.next() {
+        match stmt {
+            Stmt::Return(value) => {
+                lowered.extend(match (sink, value) {
+                    (Sink::Assign(name), Some(value))               => Some(Stmt::Assign(name.clone(), value)),
+                    (Sink::Discard, Some(value)) if has_side_effects(&value) => Some(Stmt::Expr(value)),
+                    _                                               => None,
+                });
+                return Some(lowered);
+            }
+            Stmt::If(cond, if_block, else_block) if returns(&if_block) || else_block.as_deref().is_some_and(returns) => {
+                let rest: Vec<Stmt>     = stmts.collect();
+                let else_block          = else_block.map_or(Stmt::Block(Vec::new()), |else_block| *else_block);
+                let (if_goes_on, else_goes_on) = (control_flow::completes(&if_block), control_flow::completes(&else_block));
+
+                if if_goes_on && else_goes_on && !rest.is_empty() {
+                    return None;
+                }
+
+                let branch = |block: Stmt, goes_on: bool| {
+                    let mut stmts = match block {
+                        Stmt::Block(stmts)  => stmts,
+                        other               => vec![other],
+                    };
+                    if goes_on {
+                        stmts.extend(rest.clone());
+                    }
+                    lower_returns(stmts, sink).map(Stmt::Block)
+                };
+
+                lowered.push(Stmt::If(cond, branch(*if_block, if_goes_on)?.wrap(), Some(branch(else_block, else_goes_on)?.wrap())));
+                return Some(lowered);
+            }
+            stmt if returns(&stmt) => return None,
+            stmt => lowered.push(stmt),
+        }
+    }
+
+    Some(lowered)
+}
+
+fn returns(stmt: &Stmt) -> bool {
+    let mut returns = false;
+    stmt.walk(&mut |stmt| returns |= matches!(stmt, Stmt::Return(_)));
+    returns
+}
@@ -1,10 +1,21 @@
 pub mod parser;
 pub mod optimizer;
+pub mod inlining;
+pub mod propagation;
+pub mod licm;
+pub mod loops;
+pub mod cse;
+pub mod recursion;
+pub mod dead_code;
 pub mod semantic_analyzer;
+pub mod control_flow;
+pub mod narrowing;
+pub mod lint;
 pub mod generic;
 pub mod dgen_ast;
 pub mod sqf_ast;
 pub mod dgen2sqf_ast;
 pub mod sqf_generator;
 pub mod transform;
-pub mod boxable;
\ No newline at end of file
+pub mod boxable;
+pub mod prelude;
\ No newline at end of file
@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+
+use crate::dgen_ast::*;
+use crate::boxable::Boxable;
+use crate::inlining::all_names;
+use crate::propagation::{calls_user_code, escaping_names, stepped_names};
+use crate::loops::{counting_loop, is_invariant, LoopFacts};
+
+// Loop-invariant expressions taken out of a loop, with the variables that now hold them
+type Hoisted = Vec<(Expr, String)>;
+
+/// Moves the expressions of a loop's condition and of the statements its body always runs that are
+/// pure and give the same value on every iteration, like `count(arr)` in `i < count(arr)`, into
+/// variables declared before the loop. Only expressions calling a builtin are moved, the rest costs
+/// about as much as reading the variable that would replace it.
+pub fn hoist(program: Stmt) -> Stmt {
+    let Stmt::Program(stmts) = program else { return program };
+    let mut hoister = Hoister { escaping: escaping_names(&stmts), loops: LoopFacts::of(&stmts), taken: all_names(&stmts) };
+
+    Stmt::Program(hoister.block(stmts))
+}
+
+struct Hoister {
+    // Variables some function assigns without declaring them, which any call to user code may change
+    escaping: HashSet<String>,
+    // What lowering needs to tell a loop that counts, whose bound must stay in its condition
+    loops: LoopFacts,
+    // Names in use anywhere in the program, so the new variables can't clash with them
+    taken: HashSet<String>,
+}
+
+impl Hoister {
+    fn block(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter().flat_map(|stmt| self.stmt(stmt)).collect()
+    }
+
+    // The statement with its loops rewritten, a loop preceded by the variables it got its invariants moved to
+    fn stmt(&mut self, stmt: Stmt) -> Vec<Stmt> {
+        match stmt {
+            Stmt::While(cond, block)            => {
+                let block       = self.nested(*block);
+                let variant     = self.changed_in(&[&Stmt::Expr(cond.clone()), &block]);
+                let mut hoisted = Hoisted::new();
+                let cond        = self.hoist(cond, &variant, &mut hoisted);
+                let block       = self.hoist_body(block, &variant, &mut hoisted);
+
+                declarations(hoisted).chain([Stmt::While(cond, block.wrap())]).collect()
+            },
+            Stmt::For(init, cond, step, block)  => {
+                let block       = self.nested(*block);
+                // What `init` sets isn't there yet where the declarations go
+                let variant     = self.changed_in(&[&init, &Stmt::Expr(cond.clone()), &step, &block]);
+                let mut hoisted = Hoisted::new();
+                // A loop that counts to its bound in SQF evaluates it once already
+                let cond        = if counting_loop(&init, &cond, &step, &block, &self.loops).is_some() { cond } else { self.hoist(cond, &variant, &mut hoisted) };
+                let block       = self.hoist_body(block, &variant, &mut hoisted);
+
+                declarations(hoisted).chain([Stmt::For(init, cond, step, block.wrap())]).collect()
+            },
+            Stmt::Attributed(attributes, stmt)  => {
+                let mut stmts   = self.stmt(*stmt);
+                let loop_stmt   = stmts.pop().unwrap();
+
+                stmts.push(Stmt::Attributed(attributes, loop_stmt.wrap()));
+                stmts
+            },
+            other                               => vec![self.nested(other)],
+        }
+    }
+
+    // A statement that isn't a loop itself, with the loops nested in it rewritten
+    fn nested(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Block(stmts)                  => Stmt::Block(self.block(stmts)),
+            Stmt::If(cond, if_block, else_block) => Stmt::If(cond, self.nested(*if_block).wrap(), else_block.map(|else_block| self.nested(*else_block).wrap())),
+            Stmt::FuncDef(return_type, name, type_params, params, body) => Stmt::FuncDef(return_type, name, type_params, params, self.nested(*body).wrap()),
+            Stmt::ClassDef(name, base, members) => Stmt::ClassDef(name, base, members.into_iter().map(|member| self.nested(member)).collect()),
+            Stmt::Attributed(attributes, stmt)  => Stmt::Attributed(attributes, self.nested(*stmt).wrap()),
+            other                               => other,
+        }
+    }
+
+    // The loop body with the invariants of the statements it runs on every iteration taken out
+    fn hoist_body(&mut self, block: Stmt, variant: &HashSet<String>, hoisted: &mut Hoisted) -> Stmt {
+        let Stmt::Block(stmts) = block else { return block };
+
+        Stmt::Block(stmts.into_iter().map(|stmt| match stmt {
+            Stmt::Expr(expr)                        => Stmt::Expr(self.hoist(expr, variant, hoisted)),
+            Stmt::VarDecl(typename, name, value)    => Stmt::VarDecl(typename, name, value.map(|value| self.hoist(value, variant, hoisted))),
+            Stmt::ArrayDecl(typename, name, value)  => Stmt::ArrayDecl(typename, name, value.map(|value| self.hoist(value, variant, hoisted))),
+            Stmt::Assign(name, value)               => Stmt::Assign(name, self.hoist(value, variant, hoisted)),
+            Stmt::MemberAssign(target, field, value) => Stmt::MemberAssign(target, field, self.hoist(value, variant, hoisted)),
+            Stmt::Return(value)                     => Stmt::Return(value.map(|value| self.hoist(value, variant, hoisted))),
+            Stmt::If(cond, if_block, else_block)    => Stmt::If(self.hoist(cond, variant, hoisted), if_block, else_block),
+            other                                   => other,
+        }).collect())
+    }
+
+    // The expression with its largest invariant parts replaced by the variables they're moved to
+    fn hoist(&mut self, expr: Expr, variant: &HashSet<String>, hoisted: &mut Hoisted) -> Expr {
+        if !is_invariant(&expr, variant) || !calls_builtin(&expr) {
+            return expr.map_children(&mut |child| self.hoist(child, variant, hoisted));
+        }
+
+        // The same invariant shows up again, it's read from the variable that already holds it
+        if let Some((_, name)) = hoisted.iter().find(|(value, _)| *value == expr) {
+            return Expr::Identifier(name.clone());
+        }
+
+        let name = match &expr {
+            Expr::FuncCall(builtin, _)  => self.fresh(builtin),
+            _                           => self.fresh("invariant"),
+        };
+        hoisted.push((expr, name.clone()));
+        Expr::Identifier(name)
+    }
+
+    // Variables the parts of a loop may assign while it runs
+    fn changed_in(&self, parts: &[&Stmt]) -> HashSet<String> {
+        let mut changed = HashSet::new();
+
+        for part in parts {
+            part.walk(&mut |stmt| {
+                match stmt {
+                    Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) | Stmt::Assign(name, _) => { changed.insert(name.clone()); },
+                    _ => (),
+                }
+                for expr in stmt.exprs() {
+                    changed.extend(stepped_names(expr));
+
+                    if calls_user_code(expr) {
+                        changed.extend(self.escaping.iter().cloned());
+                    }
+                }
+            });
+        }
+
+        changed
+    }
+
+    fn fresh(&mut self, name: &str) -> String {
+        let fresh = (1..).map(|n| format!("{}_{}", name, n)).find(|fresh| !self.taken.contains(fresh)).unwrap();
+        self.taken.insert(fresh.clone());
+        fresh
+    }
+}
+
+// The variables holding what was taken out of a loop, declared in front of it
+fn declarations(hoisted: Hoisted) -> impl Iterator<Item = Stmt> {
+    // Types are checked by now, SQF variables don't have one
+    hoisted.into_iter().map(|(value, name)| Stmt::VarDecl(Type::Any, name, Some(value)))
+}
+
+fn calls_builtin(expr: &Expr) -> bool {
+    let mut calls = false;
+
+    expr.walk(&mut |expr| calls |= matches!(expr, Expr::FuncCall(..)));
+
+    calls
+}
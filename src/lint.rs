@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedFunction,
+    UnusedParameter,
+    ShadowedVariable,
+    ConstantCondition,
+    SelfAssignment,
+    EmptyBlock,
+    NanComparison,
+    UnreachableCode,
+    DivisionByZero,
+    DeepRecursion,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+pub const LINTS: [Lint; 11] = [
+    Lint::UnusedVariable,
+    Lint::UnusedFunction,
+    Lint::UnusedParameter,
+    Lint::ShadowedVariable,
+    Lint::ConstantCondition,
+    Lint::SelfAssignment,
+    Lint::EmptyBlock,
+    Lint::NanComparison,
+    Lint::UnreachableCode,
+    Lint::DivisionByZero,
+    Lint::DeepRecursion,
+];
+
+// Name that stands for every lint at once
+const ALL_LINTS: &str = "warnings";
+
+// Attributes that aren't about lints but tell later passes what to do with a statement
+pub const PASS_ATTRIBUTES: [&str; 3] = ["export", "inline", "noinline"];
+
+impl Lint {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariable    => "unused_variable",
+            Lint::UnusedFunction    => "unused_function",
+            Lint::UnusedParameter   => "unused_parameter",
+            Lint::ShadowedVariable  => "shadowed_variable",
+            Lint::ConstantCondition => "constant_condition",
+            Lint::SelfAssignment    => "self_assignment",
+            Lint::EmptyBlock        => "empty_block",
+            Lint::NanComparison     => "nan_comparison",
+            Lint::UnreachableCode   => "unreachable_code",
+            Lint::DivisionByZero    => "division_by_zero",
+            Lint::DeepRecursion     => "deep_recursion",
+        }
+    }
+
+    /// Resolves a lint name, where `warnings` selects all of them.
+    pub fn parse(name: &str) -> Result<Vec<Lint>, String> {
+        if name == ALL_LINTS {
+            return Ok(LINTS.to_vec());
+        }
+
+        LINTS.iter().find(|lint| lint.name() == name).map(|lint| vec![*lint]).ok_or(format!("Unknown lint '{}'", name))
+    }
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Result<Level, String> {
+        match name {
+            "allow" => Ok(Level::Allow),
+            "warn"  => Ok(Level::Warn),
+            "deny"  => Ok(Level::Deny),
+            _       => Err(format!("Unknown lint level '{}'", name)),
+        }
+    }
+}
+
+/// Lint levels set for a whole compilation, from the project config and the command line.
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    levels: HashMap<Lint, Level>,
+    // Deepest recursion `deep_recursion` lets through
+    recursion_limit: usize,
+}
+
+// Levels of `call` a script can nest before SQF gives up on it, with room to spare
+pub const RECURSION_LIMIT: usize = 1000;
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self { levels: HashMap::new(), recursion_limit: RECURSION_LIMIT }
+    }
+}
+
+impl LintConfig {
+    pub fn set(&mut self, name: &str, level: Level) -> Result<(), String> {
+        for lint in Lint::parse(name)? {
+            self.levels.insert(lint, level);
+        }
+        Ok(())
+    }
+
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+
+    pub fn set_recursion_limit(&mut self, depth: usize) {
+        self.recursion_limit = depth;
+    }
+
+    pub fn recursion_limit(&self) -> usize {
+        self.recursion_limit
+    }
+
+    /// Reads the `[lints]` table of a project config:
+    ///
+    /// ```toml
+    /// [lints]
+    /// unused_variable = "allow"
+    /// constant_condition = "deny"
+    /// ```
+    pub fn from_project(source: &str) -> Result<LintConfig, String> {
+        let mut config  = LintConfig::default();
+        let mut section = String::new();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+
+            if section != "lints" {
+                continue;
+            }
+
+            let (name, level) = line.split_once('=').ok_or(format!("Line {}: expected `lint = \"level\"`", number + 1))?;
+            let level = level.trim().trim_matches('"');
+
+            Level::parse(level)
+                .and_then(|level| config.set(name.trim(), level))
+                .map_err(|error| format!("Line {}: {}", number + 1, error))?;
+        }
+
+        Ok(config)
+    }
+}
+
+/// Lint levels in effect at some point of the program: the config, overridden by the
+/// `#[allow(...)]`, `#[warn(...)]` and `#[deny(...)]` attributes around it.
+#[derive(Debug, Clone)]
+pub struct LintLevels {
+    config: LintConfig,
+    overrides: Vec<Vec<(Lint, Level)>>,
+}
+
+impl LintLevels {
+    pub fn new(config: LintConfig) -> Self {
+        Self { config, overrides: Vec::new() }
+    }
+
+    pub fn config(&self) -> &LintConfig {
+        &self.config
+    }
+
+    pub fn level(&self, lint: Lint) -> Level {
+        self.overrides
+            .iter()
+            .rev()
+            .find_map(|overrides| overrides.iter().rev().find(|(l, _)| *l == lint).map(|(_, level)| *level))
+            .unwrap_or(self.config.level(lint))
+    }
+
+    /// Applies a statement's attributes until the matching `exit`, returning errors for the ones it doesn't know.
+    pub fn enter(&mut self, attributes: &[(String, Vec<String>)]) -> Vec<String> {
+        let mut overrides   = Vec::new();
+        let mut errors      = Vec::new();
+
+        for (name, args) in attributes {
+            if PASS_ATTRIBUTES.contains(&name.as_str()) {
+                if !args.is_empty() {
+                    errors.push(format!("Attribute '{}' takes no arguments", name));
+                }
+                continue;
+            }
+
+            let level = match Level::parse(name) {
+                Ok(level)   => level,
+                Err(_)      => {
+                    errors.push(format!("Unknown attribute '{}'", name));
+                    continue;
+                }
+            };
+
+            for arg in args {
+                match Lint::parse(arg) {
+                    Ok(lints)   => overrides.extend(lints.into_iter().map(|lint| (lint, level))),
+                    Err(error)  => errors.push(error),
+                }
+            }
+        }
+
+        self.overrides.push(overrides);
+        errors
+    }
+
+    pub fn exit(&mut self) {
+        self.overrides.pop();
+    }
+
+    /// Files a lint under errors or warnings depending on its level.
+    pub fn report(&self, lint: Lint, message: &str, errors: &mut Vec<String>, warnings: &mut Vec<String>) {
+        report_at(self.level(lint), lint, message, errors, warnings);
+    }
+}
+
+/// Like `LintLevels::report`, for lints whose level was captured earlier than they could be reported.
+pub fn report_at(level: Level, lint: Lint, message: &str, errors: &mut Vec<String>, warnings: &mut Vec<String>) {
+    let message = format!("{} [{}]", message, lint.name());
+
+    match level {
+        Level::Allow    => (),
+        Level::Warn     => warnings.push(message),
+        Level::Deny     => errors.push(message),
+    }
+}
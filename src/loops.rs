@@ -0,0 +1,153 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::generic::*;
+use crate::dgen_ast::*;
+use crate::boxable::Boxable;
+use crate::optimizer::is_pure;
+use crate::propagation;
+
+/// What deciding whether a loop counts needs to know about the rest of the program.
+#[derive(Debug, Clone, Default)]
+pub struct LoopFacts {
+    // Variables some function assigns without declaring them, which any call to user code may change
+    escaping: HashSet<String>,
+    // Variables declared once, from a whole number, and never changed, like the bounds LICM moves out
+    whole: HashSet<String>,
+}
+
+impl LoopFacts {
+    pub fn of(program: &[Stmt]) -> Self {
+        let mut declared: HashMap<String, usize>    = HashMap::new();
+        let mut whole                               = HashSet::new();
+        let mut changed                             = HashSet::new();
+
+        for stmt in program {
+            stmt.walk(&mut |stmt| {
+                match stmt {
+                    Stmt::VarDecl(_, name, value) | Stmt::ArrayDecl(_, name, value) => {
+                        *declared.entry(name.clone()).or_default() += 1;
+                        if value.as_ref().is_some_and(|value| is_whole(value, &HashSet::new())) {
+                            whole.insert(name.clone());
+                        }
+                    },
+                    Stmt::FuncDef(_, _, _, params, _) => for (_, name, _) in params {
+                        *declared.entry(name.clone()).or_default() += 1;
+                    },
+                    Stmt::Assign(name, _) => { changed.insert(name.clone()); },
+                    _ => (),
+                }
+                for expr in stmt.exprs() {
+                    changed.extend(propagation::stepped_names(expr));
+                }
+            });
+        }
+
+        whole.retain(|name| declared[name] == 1 && !changed.contains(name));
+        Self { escaping: propagation::escaping_names(program), whole }
+    }
+}
+
+/// A `for` loop that counts a number up by a constant to a bound, which SQF can run as
+/// `for "_i" from start to end step step`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountingLoop {
+    pub var: String,
+    pub start: Expr,
+    // The last value the loop runs with, SQF includes it
+    pub end: Expr,
+    // None when it counts up by one
+    pub step: Option<f64>,
+}
+
+/// The counting loop a `for` loop is, if it is one.
+///
+/// SQF's counting form evaluates the bound once and keeps its own count, so the body must not assign
+/// the variable or anything the bound reads. Its end is included, so `i < n` ends at `n - 1`, which
+/// only counts the same when `i` and `n` are whole numbers.
+pub fn counting_loop(init: &Stmt, cond: &Expr, step: &Stmt, block: &Stmt, facts: &LoopFacts) -> Option<CountingLoop> {
+    let Stmt::VarDecl(_, var, Some(start)) = init else { return None };
+    let counter = Expr::Identifier(var.clone());
+
+    let (op, bound) = match cond {
+        Expr::BinaryOp(op @ (Operator::Lt | Operator::Lte), left, bound) if **left == counter => (op, bound),
+        _ => return None,
+    };
+
+    let increment = match step {
+        Stmt::Expr(Expr::UnaryOp(Operator::Inc, operand, _)) if **operand == counter => 1.0,
+        Stmt::Assign(name, Expr::BinaryOp(Operator::Add, left, right)) if name == var && **left == counter => match **right {
+            Expr::Number(n) if n > 0.0  => n,
+            _                           => return None,
+        },
+        _ => return None,
+    };
+
+    let mut assigned = HashSet::new();
+
+    block.walk(&mut |stmt| {
+        match stmt {
+            Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) | Stmt::Assign(name, _) => { assigned.insert(name.clone()); },
+            _ => (),
+        }
+        for expr in stmt.exprs() {
+            assigned.extend(propagation::stepped_names(expr));
+        }
+    });
+
+    if assigned.contains(var) {
+        return None;
+    }
+    assigned.insert(var.clone());
+
+    // A function the body calls may assign the variables functions assign without declaring, as LICM assumes
+    if calls_user_code(block) {
+        assigned.extend(facts.escaping.iter().cloned());
+    }
+
+    if !is_invariant(bound, &assigned) {
+        return None;
+    }
+
+    let end = match (op, &**bound) {
+        (Operator::Lte, bound)                                          => bound.clone(),
+        _ if !is_whole(start, &facts.whole) || increment.fract() != 0.0 => return None,
+        (_, Expr::Number(n)) if n.fract() == 0.0                        => Expr::Number(n - 1.0),
+        (_, bound) if is_whole(bound, &facts.whole)                     => Expr::BinaryOp(Operator::Sub, bound.clone().wrap(), Expr::Number(1.0).wrap()),
+        _                                                               => return None,
+    };
+
+    Some(CountingLoop { var: var.clone(), start: start.clone(), end, step: (increment != 1.0).then_some(increment) })
+}
+
+/// Whether the expression gives the same value on every iteration of a loop assigning `variant`: it
+/// changes nothing and reads nothing the loop changes, object fields included, which a method or a
+/// member assignment can change without anything saying so.
+pub fn is_invariant(expr: &Expr, variant: &HashSet<String>) -> bool {
+    let mut invariant = is_pure(expr);
+
+    expr.walk(&mut |expr| invariant &= match expr {
+        Expr::Identifier(name) | Expr::ArrayAccess(name, _) => !variant.contains(name),
+        Expr::Member(..)                                    => false,
+        _                                                   => true,
+    });
+
+    invariant
+}
+
+// Only holds whole numbers
+fn is_whole(expr: &Expr, whole: &HashSet<String>) -> bool {
+    match expr {
+        Expr::Number(n)             => n.fract() == 0.0,
+        Expr::FuncCall(name, _)     => name == "count" || name == "floor",
+        Expr::Identifier(name)      => whole.contains(name),
+        _                           => false,
+    }
+}
+
+fn calls_user_code(block: &Stmt) -> bool {
+    let mut calls = false;
+
+    block.walk_exprs(&mut |expr| calls |= propagation::calls_user_code(expr));
+
+    calls
+}
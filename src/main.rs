@@ -1,24 +1,13 @@
-mod parser;
-mod optimizer;
-mod semantic_analyzer;
-mod generic;
-mod dgen_ast;
-mod sqf_ast;
-mod dgen2sqf_ast;
-mod sqf_generator;
-mod transform;
-mod boxable;
-
 use std::io;
 
-use crate::transform::*;
-use crate::sqf_generator::*;
-use clap::Parser;
+use dgen::*;
+use dgen::transform::*;
+use dgen::sqf_generator::*;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 
 #[derive(Parser, Debug)]
 #[command(name = "dGen to SQF transpiler", version = "v0.1", author = "Bit")]
 #[command(about = "Transpiles dGen to SQF", long_about = None)]
-
 struct Cli {
     #[arg(short, long)]
     input: String,
@@ -26,29 +15,107 @@ struct Cli {
     output: String,
     #[arg(short, long, default_value = "false")]
     minify: bool,
+    /// Project config with a `[lints]` table; defaults to dgen.toml next to the input
+    #[arg(short, long)]
+    config: Option<String>,
+    /// Silences a lint (`warnings` for all of them)
+    #[arg(short = 'A', long = "allow", value_name = "LINT")]
+    allow: Vec<String>,
+    /// Reports a lint as a warning
+    #[arg(short = 'W', long = "warn", value_name = "LINT")]
+    warn: Vec<String>,
+    /// Reports a lint as an error
+    #[arg(short = 'D', long = "deny", value_name = "LINT")]
+    deny: Vec<String>,
+    /// Deepest recursion `deep_recursion` lets through without a warning
+    #[arg(long, value_name = "DEPTH")]
+    recursion_limit: Option<usize>,
+    /// Optimization level: 0, 1, 2, or s to keep the output small
+    #[arg(short = 'O', value_name = "LEVEL", default_value = "2")]
+    opt_level: String,
+    /// Runs these optimization passes in this order instead of the ones the level picks
+    #[arg(long, value_name = "PASS", value_delimiter = ',')]
+    passes: Vec<String>,
+    /// Prints the program as SQF after every run of the pass
+    #[arg(long, value_name = "PASS")]
+    print_after: Vec<String>,
+}
+
+// Command line levels override the project config, and a later flag overrides an earlier one
+fn lint_config(args: &Cli, matches: &ArgMatches) -> io::Result<lint::LintConfig> {
+    let invalid     = |error: String| io::Error::new(io::ErrorKind::InvalidInput, error);
+    let default     = std::path::Path::new(&args.input).with_file_name("dgen.toml");
+
+    let mut config  = match &args.config {
+        Some(path)                  => lint::LintConfig::from_project(&std::fs::read_to_string(path)?).map_err(invalid)?,
+        None if default.exists()    => lint::LintConfig::from_project(&std::fs::read_to_string(default)?).map_err(invalid)?,
+        None                        => lint::LintConfig::default(),
+    };
+
+    let mut levels = Vec::new();
+    for (id, names, level) in [("allow", &args.allow, lint::Level::Allow), ("warn", &args.warn, lint::Level::Warn), ("deny", &args.deny, lint::Level::Deny)] {
+        levels.extend(matches.indices_of(id).into_iter().flatten().zip(names).map(|(index, name)| (index, name, level)));
+    }
+    levels.sort_by_key(|(index, _, _)| *index);
+
+    for (_, name, level) in levels {
+        config.set(name, level).map_err(invalid)?;
+    }
+
+    if let Some(depth) = args.recursion_limit {
+        config.set_recursion_limit(depth);
+    }
+
+    Ok(config)
+}
+
+// Passes named on the command line override the ones the level picks
+fn pass_manager(args: &Cli) -> io::Result<optimizer::PassManager> {
+    let invalid     = |error: String| io::Error::new(io::ErrorKind::InvalidInput, error);
+    let mut manager = optimizer::PassManager::new(optimizer::Level::from_name(&args.opt_level).map_err(invalid)?);
+
+    if !args.passes.is_empty() {
+        manager.set_passes(args.passes.iter().map(|name| optimizer::Pass::from_name(name)).collect::<Result<_, _>>().map_err(invalid)?);
+    }
+
+    for name in &args.print_after {
+        manager.print_after(optimizer::Pass::from_name(name).map_err(invalid)?);
+    }
+
+    Ok(manager)
 }
 
 fn main() -> io::Result<()> {
-    let args = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let args    = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
 
-    let mut semantic_analyzer   = semantic_analyzer::SemanticAnalyzer::new();
+    let mut semantic_analyzer   = semantic_analyzer::SemanticAnalyzer::with_lints(lint_config(&args, &matches)?);
+    let pass_manager            = pass_manager(&args)?;
+    semantic_analyzer.set_passes(pass_manager.passes());
 
     let raw_root                = parser::parse_file(args.input)?;
-    let optimized_root          = optimizer::optimize(raw_root);
-    let validated_root          = semantic_analyzer.analyze(optimized_root);
+    let validated_root          = semantic_analyzer.analyze(raw_root);
     let errors                  = semantic_analyzer.errors();
 
-    if errors.len() > 0 {
+    for warning in semantic_analyzer.warnings() {
+        println!("Warning: {}", warning);
+    }
+
+    if !errors.is_empty() {
         for error in errors {
             println!("Semantic error: {}", error);
         }
 
         Err(io::Error::new(io::ErrorKind::InvalidData, "Semantic errors found"))
     } else {
-        let sqf_ast : sqf_ast::Stmt = validated_root.transform();
+        let optimized_root          = pass_manager.run(validated_root, &mut |pass, round, program| {
+            let sqf_ast : sqf_ast::Stmt = program.transform();
+            println!("// After {} (round {})\n{}\n", pass.name(), round, sqf_ast.generate_sqf(0, args.minify));
+        });
+        let sqf_ast : sqf_ast::Stmt = optimized_root.transform();
         let code                    = sqf_ast.generate_sqf(0, args.minify);
         std::fs::write(args.output, code)?;
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,36 @@
+use crate::generic::*;
+use crate::dgen_ast::*;
+
+/// Returns the variables known to be non-null when `cond` evaluates to `outcome`.
+pub fn non_null_facts(cond: &Expr, outcome: bool) -> Vec<String> {
+    match cond {
+        Expr::BinaryOp(op @ (Operator::Eq | Operator::Neq), value, null) if matches!(**null, Expr::Null(_)) => match &**value {
+            Expr::Identifier(name) if (*op == Operator::Neq) == outcome => vec![name.clone()],
+            _ => Vec::new(),
+        },
+        Expr::BinaryOp(Operator::And, left, right) if outcome   => [non_null_facts(left, true), non_null_facts(right, true)].concat(),
+        Expr::BinaryOp(Operator::Or, left, right) if !outcome   => [non_null_facts(left, false), non_null_facts(right, false)].concat(),
+        Expr::UnaryOp(Operator::Not, operand, _)                => non_null_facts(operand, !outcome),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the `is` tests that hold when `cond` evaluates to `outcome`: the variable, the type and
+/// whether the variable is of that type.
+pub fn type_facts(cond: &Expr, outcome: bool) -> Vec<(String, Type, bool)> {
+    match cond {
+        Expr::Is(value, typename) => match &**value {
+            Expr::Identifier(name) => vec![(name.clone(), typename.clone(), outcome)],
+            _ => Vec::new(),
+        },
+        Expr::BinaryOp(Operator::And, left, right) if outcome   => [type_facts(left, true), type_facts(right, true)].concat(),
+        Expr::BinaryOp(Operator::Or, left, right) if !outcome   => [type_facts(left, false), type_facts(right, false)].concat(),
+        Expr::UnaryOp(Operator::Not, operand, _)                => type_facts(operand, !outcome),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns every variable `cond` says something about when it evaluates to `outcome`.
+pub fn checked_names(cond: &Expr, outcome: bool) -> Vec<String> {
+    non_null_facts(cond, outcome).into_iter().chain(type_facts(cond, outcome).into_iter().map(|(name, _, _)| name)).collect()
+}
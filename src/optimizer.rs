@@ -1,9 +1,17 @@
 use crate::generic::*;
 use crate::dgen_ast::*;
 use crate::boxable::Boxable;
+use crate::prelude::{self, Effect};
+use crate::inlining;
+use crate::propagation;
+use crate::licm;
+use crate::cse;
+use crate::recursion;
+use crate::dead_code;
 
 impl Expr {
-    fn eval(self) -> Expr {
+    /// The expression with every subtree made only of literals replaced by its value.
+    pub fn eval(self) -> Expr {
         use Expr::*;
         use Operator::*;
 
@@ -15,7 +23,7 @@ impl Expr {
                 match (op.clone(), e_expr.clone()) {
                     (Neg, Number(n)) => Number(-n),
                     (Not, Bool(b))   => Bool(!b),
-                    _ => UnaryOp(op, e_expr.wrap(), is_postfix),
+                    _ => simplify(UnaryOp(op, e_expr.wrap(), is_postfix)),
                 }
             },
             BinaryOp(op, left, right) => {
@@ -23,17 +31,16 @@ impl Expr {
                 let e_right = right.eval();
 
                 match (op.clone(), e_left.clone(), e_right.clone()) {
-                    (Add, Number(l), Number(r)) => Number(l + r),
-                    (Sub, Number(l), Number(r)) => Number(l - r),
-                    (Mul, Number(l), Number(r)) => Number(l * r),
-                    (Div, Number(l), Number(r)) => Number(l / r),
-                    (Mod, Number(l), Number(r)) => Number(l % r),
-                    (Eq,  Number(l), Number(r)) => Bool(l == r),
-                    (Neq, Number(l), Number(r)) => Bool(l != r),
-                    (Gt,  Number(l), Number(r)) => Bool(l > r),
-                    (Lt,  Number(l), Number(r)) => Bool(l < r),
-                    (Lte, Number(l), Number(r)) => Bool(l <= r),
-                    (Gte, Number(l), Number(r)) => Bool(l >= r),
+                    (Add | Sub | Mul | Div | Mod, Number(l), Number(r)) => match sqf_arithmetic(&op, l, r) {
+                        Some(n) => Number(n),
+                        None    => BinaryOp(op, e_left.wrap(), e_right.wrap()),
+                    },
+                    (Eq,  Number(l), Number(r)) => Bool(l as f32 == r as f32),
+                    (Neq, Number(l), Number(r)) => Bool(l as f32 != r as f32),
+                    (Gt,  Number(l), Number(r)) => Bool(l as f32 > r as f32),
+                    (Lt,  Number(l), Number(r)) => Bool((l as f32) < r as f32),
+                    (Lte, Number(l), Number(r)) => Bool(l as f32 <= r as f32),
+                    (Gte, Number(l), Number(r)) => Bool(l as f32 >= r as f32),
                     (And, Bool(l), Bool(r))     => Bool(l && r),
                     (Or,  Bool(l), Bool(r))     => Bool(l || r),
                     (Eq,  Bool(l), Bool(r))     => Bool(l == r),
@@ -42,45 +49,387 @@ impl Expr {
                     (Eq,  String(l), String(r)) => Bool(l == r),
                     (Neq, String(l), String(r)) => Bool(l != r),
 
-                    _ => BinaryOp(op, e_left.wrap(), e_right.wrap()),
+                    _ => simplify(BinaryOp(op, e_left.wrap(), e_right.wrap())),
+                }
+            },
+            FuncCall(name, args)                => {
+                let e_args : Vec<Expr> = args.into_iter().map(|e| e.eval()).collect();
+
+                match e_args.as_slice() {
+                    [arg] => fold_conversion(&name, arg).unwrap_or(FuncCall(name, e_args)),
+                    _ => FuncCall(name, e_args),
+                }
+            },
+            New(class, args)                    => New(class, args.into_iter().map(|e| e.eval()).collect()),
+            Member(target, field)               => Member(target.eval().wrap(), field),
+            MethodCall(target, method, args)    => MethodCall(target.eval().wrap(), method, args.into_iter().map(|e| e.eval()).collect()),
+            NamedArg(name, value)               => NamedArg(name, value.eval().wrap()),
+            Is(value, typename)                 => Is(value.eval().wrap(), typename),
+            Cast(value, typename)               => {
+                let e_value = value.eval();
+
+                match e_value.literal_type() {
+                    Some(t) if t == typename    => e_value,
+                    Some(t)                     => prelude::conversion(&t, &typename)
+                                                    .and_then(|builtin| fold_conversion(builtin, &e_value))
+                                                    .unwrap_or(Cast(e_value.wrap(), typename)),
+                    None                        => Cast(e_value.wrap(), typename),
                 }
             },
-            FuncCall(name, args)                => FuncCall(name, args.into_iter().map(|e| e.eval()).collect()),
             _ => self,
         }
     }
+
+    fn literal_type(&self) -> Option<Type> {
+        match self {
+            Expr::Number(_) => Some(Type::Number),
+            Expr::Bool(_)   => Some(Type::Boolean),
+            Expr::String(_) => Some(Type::String),
+            _               => None,
+        }
+    }
+}
+
+// Identities that hold whatever the operands that aren't literals evaluate to, once the literal subtrees are folded
+fn simplify(expr: Expr) -> Expr {
+    use Expr::*;
+    use Operator::*;
+
+    match expr {
+        UnaryOp(Not, operand, is_postfix) => match *operand {
+            UnaryOp(Not, inner, _)                                      => *inner,
+            // De Morgan, when it cancels out at least one `!`
+            BinaryOp(op @ (And | Or), left, right) if is_not(&left) || is_not(&right) => {
+                let flipped = if op == And { Or } else { And };
+                simplify(BinaryOp(flipped, negate(*left).wrap(), negate(*right).wrap()))
+            },
+            operand                                                     => UnaryOp(Not, operand.wrap(), is_postfix),
+        },
+        BinaryOp(op, left, right) => match (op.clone(), *left, *right) {
+            (Add, x, Number(n)) | (Add, Number(n), x) | (Sub, x, Number(n)) if n == 0.0 => x,
+            (Mul, x, Number(n)) | (Mul, Number(n), x) | (Div, x, Number(n)) if n == 1.0 => x,
+            (Mul, x, Number(n)) | (Mul, Number(n), x) if n == 0.0 && !has_side_effects(&x) => Number(0.0),
+            (Sub, x, y) if x == y && is_pure(&x)                        => Number(0.0),
+            // Chains with constants at both ends fold into one constant: (x + 2) + 3 is x + 5
+            (Add | Mul, BinaryOp(inner, x, a), Number(b)) | (Add | Mul, Number(b), BinaryOp(inner, x, a)) if inner == op => {
+                match constant(&a).and_then(|a| sqf_arithmetic(&op, a, b)) {
+                    Some(c) => simplify(BinaryOp(op, x, Number(c).wrap())),
+                    None    => BinaryOp(op, BinaryOp(inner, x, a).wrap(), Number(b).wrap()),
+                }
+            },
+            (Add, BinaryOp(Sub, x, a), Number(b)) | (Add, Number(b), BinaryOp(Sub, x, a)) => {
+                match constant(&a).and_then(|a| sqf_arithmetic(&Sub, b, a)) {
+                    Some(c) => simplify(BinaryOp(Add, x, Number(c).wrap())),
+                    None    => BinaryOp(Add, BinaryOp(Sub, x, a).wrap(), Number(b).wrap()),
+                }
+            },
+            (Sub, BinaryOp(inner @ (Add | Sub), x, a), Number(b)) => {
+                let combine = if inner == Add { Sub } else { Add };
+
+                match constant(&a).and_then(|a| sqf_arithmetic(&combine, a, b)) {
+                    Some(c) => simplify(BinaryOp(inner, x, Number(c).wrap())),
+                    None    => BinaryOp(Sub, BinaryOp(inner, x, a).wrap(), Number(b).wrap()),
+                }
+            },
+            (Add, BinaryOp(Add, x, a), String(b)) if matches!(*a, String(_)) => {
+                let String(a) = *a else { unreachable!() };
+                BinaryOp(Add, x, String(a + &b).wrap())
+            },
+            // Multiplying is cheaper than dividing, and exact when the divisor is a power of two
+            (Div, x, Number(n)) if is_power_of_two(n)                   => BinaryOp(Mul, x.wrap(), Number(1.0 / n).wrap()),
+            (op, left, right)                                           => BinaryOp(op, left.wrap(), right.wrap()),
+        },
+        other => other,
+    }
+}
+
+fn constant(expr: &Expr) -> Option<f64> {
+    match expr {
+        Expr::Number(n) => Some(*n),
+        _               => None,
+    }
 }
 
+fn is_not(expr: &Expr) -> bool {
+    matches!(expr, Expr::UnaryOp(Operator::Not, _, _))
+}
+
+fn negate(expr: Expr) -> Expr {
+    simplify(Expr::UnaryOp(Operator::Not, expr.wrap(), false))
+}
+
+fn is_power_of_two(n: f64) -> bool {
+    n.is_normal() && n.to_bits() & ((1 << 52) - 1) == 0
+}
+
+// Arithmetic on two numbers in the 32-bit floats SQF computes with. Nothing for what SQF only knows at
+// run time: a division by zero, which it reports as an error, or a result too big for a 32-bit float.
+fn sqf_arithmetic(op: &Operator, l: f64, r: f64) -> Option<f64> {
+    let (l, r) = (l as f32, r as f32);
+
+    let result = match op {
+        Operator::Add                               => l + r,
+        Operator::Sub                               => l - r,
+        Operator::Mul                               => l * r,
+        Operator::Div | Operator::Mod if r == 0.0   => return None,
+        Operator::Div                               => l / r,
+        // Like SQF's `mod`, the remainder has the sign of the dividend
+        Operator::Mod                               => l % r,
+        _                                           => return None,
+    };
+
+    result.is_finite().then_some(result as f64)
+}
+
+// Evaluates a conversion builtin on a literal; strings that don't hold a number are left for SQF
+fn fold_conversion(builtin: &str, value: &Expr) -> Option<Expr> {
+    match (builtin, value) {
+        ("str", Expr::Number(n))            => Some(Expr::String(sqf_str(*n as f32))),
+        ("str", Expr::Bool(b))              => Some(Expr::String(b.to_string())),
+        ("parseNumber", Expr::String(s))    => s.trim().parse::<f32>().ok().filter(|n| n.is_finite()).map(|n| Expr::Number(n as f64)),
+        _                                   => None,
+    }
+}
+
+/// Formats a number the way SQF's `str` does, like C's `%g`: six significant digits, in scientific
+/// notation when the exponent is below -4 or above 5, without trailing zeros.
+pub fn sqf_str(n: f32) -> String {
+    if n == 0.0 {
+        return "0".to_string();
+    }
+
+    // The exponent after rounding to six digits, which can carry into the next power of ten
+    let scientific      = format!("{:.5e}", n);
+    let (mantissa, exp) = scientific.split_once('e').unwrap();
+    let exp: i32        = exp.parse().unwrap();
+    let trim            = |digits: &str| if digits.contains('.') { digits.trim_end_matches('0').trim_end_matches('.').to_string() } else { digits.to_string() };
+
+    if !(-4..6).contains(&exp) {
+        format!("{}e{}{:02}", trim(mantissa), if exp < 0 { '-' } else { '+' }, exp.abs())
+    } else {
+        trim(&format!("{:.*}", (5 - exp) as usize, n))
+    }
+}
+
+/// Value of a condition that can't change at run time.
+pub fn constant_condition(cond: &Expr) -> Option<bool> {
+    match cond.clone().eval() {
+        Expr::Bool(b)   => Some(b),
+        _               => None,
+    }
+}
+
+/// Value of a number expression that can't change at run time.
+pub fn constant_number(expr: &Expr) -> Option<f64> {
+    match expr.clone().eval() {
+        Expr::Number(n) => Some(n),
+        _               => None,
+    }
+}
+
+/// Whether evaluating the expression can change anything: `++`/`--` assign, and builtins that
+/// write, user functions, methods and constructors all may.
+pub fn has_side_effects(expr: &Expr) -> bool {
+    let mut effects = false;
+
+    expr.walk(&mut |expr| effects |= match expr {
+        Expr::UnaryOp(Operator::Inc | Operator::Dec, _, _)  => true,
+        Expr::FuncCall(name, _)                             => prelude::find(name).is_none_or(|builtin| builtin.effect == Effect::Writes),
+        Expr::New(..) | Expr::MethodCall(..)                => true,
+        _                                                   => false,
+    });
+
+    effects
+}
+
+/// Whether the expression changes nothing and gives the same value every time it's evaluated: it only
+/// reads variables and fields and calls builtins that are pure.
+pub fn is_pure(expr: &Expr) -> bool {
+    let mut pure = true;
+
+    expr.walk(&mut |expr| pure &= match expr {
+        Expr::UnaryOp(Operator::Inc | Operator::Dec, _, _)  => false,
+        Expr::Identifier(name)                              => prelude::find(name).is_none_or(|builtin| builtin.effect == Effect::Pure),
+        Expr::FuncCall(name, _)                             => prelude::find(name).is_some_and(|builtin| builtin.effect == Effect::Pure),
+        Expr::New(..) | Expr::MethodCall(..)                => false,
+        _                                                   => true,
+    });
+
+    pure
+}
+
+// Rounds of passes after which the optimizer gives up on reaching a fixpoint
+const MAX_ROUNDS: usize = 16;
+
+/// A transformation of the whole program the optimizer can run, picked by name with `--passes`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+    Fold,
+    TailCall,
+    Inline,
+    Propagate,
+    Cse,
+    Licm,
+    Dce,
+}
+
+const PASSES: [Pass; 7] = [Pass::Fold, Pass::TailCall, Pass::Inline, Pass::Propagate, Pass::Cse, Pass::Licm, Pass::Dce];
+
+impl Pass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Fold      => "fold",
+            Pass::TailCall  => "tailcall",
+            Pass::Inline    => "inline",
+            Pass::Propagate => "propagate",
+            Pass::Cse       => "cse",
+            Pass::Licm      => "licm",
+            Pass::Dce       => "dce",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Pass, String> {
+        PASSES.into_iter().find(|pass| pass.name() == name).ok_or_else(|| format!(
+            "Unknown optimization pass '{}', expected one of {}", name, PASSES.map(|pass| pass.name()).join(", ")
+        ))
+    }
+}
+
+/// How much the optimizer does, picked with `-O`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    // `-O0`, the program is left as written
+    None,
+    // `-O1`, only passes that never make the program bigger
+    Basic,
+    // `-O2`, everything
+    Full,
+    // `-Os`, everything that saves room, inlining only what takes no more than a call
+    Size,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Result<Level, String> {
+        match name {
+            "0" => Ok(Level::None),
+            "1" => Ok(Level::Basic),
+            "2" => Ok(Level::Full),
+            "s" => Ok(Level::Size),
+            _   => Err(format!("Unknown optimization level '{}', expected 0, 1, 2 or s", name)),
+        }
+    }
+
+    fn passes(&self) -> Vec<Pass> {
+        match self {
+            Level::None                 => Vec::new(),
+            Level::Basic                => vec![Pass::Fold, Pass::Propagate, Pass::Dce],
+            Level::Full                 => vec![Pass::Fold, Pass::TailCall, Pass::Inline, Pass::Propagate, Pass::Cse, Pass::Licm, Pass::Dce],
+            Level::Size                 => vec![Pass::Fold, Pass::TailCall, Pass::Inline, Pass::Propagate, Pass::Dce],
+        }
+    }
+}
+
+/// Runs passes in order, round after round, until one leaves the program as it was.
+#[derive(Debug, Clone)]
+pub struct PassManager {
+    passes: Vec<Pass>,
+    // Passes whose output is handed to the dump callback
+    print_after: Vec<Pass>,
+    // Largest body the inliner copies into callers without being asked to
+    inline_size: usize,
+}
+
+impl PassManager {
+    pub fn new(level: Level) -> Self {
+        let inline_size = if level == Level::Size { inlining::CALL_SIZE } else { inlining::INLINE_SIZE };
+        Self { passes: level.passes(), print_after: Vec::new(), inline_size }
+    }
+
+    /// Replaces the passes the level picked.
+    pub fn set_passes(&mut self, passes: Vec<Pass>) {
+        self.passes = passes;
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    pub fn print_after(&mut self, pass: Pass) {
+        self.print_after.push(pass);
+    }
+
+    /// Optimizes the program, calling `dump` with the pass, the round and the program after each run of
+    /// a pass given to `print_after`.
+    pub fn run(&self, program: Stmt, dump: &mut dyn FnMut(Pass, usize, &Stmt)) -> Stmt {
+        let mut program = program;
+
+        for round in 1..=MAX_ROUNDS {
+            let before = program.clone();
+
+            for pass in &self.passes {
+                program = self.run_pass(*pass, program);
+
+                if self.print_after.contains(pass) {
+                    dump(*pass, round, &program);
+                }
+            }
+
+            if program == before {
+                break;
+            }
+        }
+
+        program
+    }
+
+    fn run_pass(&self, pass: Pass, program: Stmt) -> Stmt {
+        match pass {
+            Pass::Fold      => fold(program),
+            Pass::TailCall  => recursion::eliminate_tail_calls(program),
+            Pass::Inline    => inlining::inline(program, self.inline_size),
+            Pass::Propagate => propagation::propagate(program),
+            Pass::Cse       => cse::eliminate(program),
+            Pass::Licm      => licm::hoist(program),
+            Pass::Dce       => dead_code::eliminate(program),
+        }
+    }
+}
+
+/// Optimizes the program with every pass, as `-O2` does.
 pub fn optimize(root: Stmt) -> Stmt {
+    PassManager::new(Level::Full).run(root, &mut |_, _, _| ())
+}
+
+// Folds the constant expressions of every statement
+fn fold(root: Stmt) -> Stmt {
     use Stmt::*;
 
     match root {
         Block(stmts)                    => Block(stmts
                                                     .into_iter()
-                                                    .map(|stmt| optimize(stmt))
+                                                    .map(fold)
                                                     .collect()),
         Program(stmts)                  => Program(stmts
                                                     .into_iter()
-                                                    .map(|stmt| optimize(stmt))
+                                                    .map(fold)
                                                     .collect()),
         Expr(expr)                      => Expr(expr.eval()),
         Assign(name, value)             => Assign(name, value.eval()),
-        VarDecl(typename, name, value)  => {
-            let res = match value.clone() {
-                Option::Some(v) => Some(v.eval()),
-                Option::None    => None
-            };
-
-            VarDecl(typename, name, res)
-        },
-        FuncDef(return_type, name, params, body)    => FuncDef(return_type, name, params, optimize(*body).wrap()),
-        Return(expr)                                => Return(expr.eval()),
-        If(condition, if_block, else_block)         => If(condition.eval(), optimize(*if_block).wrap(), else_block.map(|b| optimize(*b).wrap())),
-        For(init, condition, step, block)           => For(optimize(*init).wrap(), condition.eval(), optimize(*step).wrap(), optimize(*block).wrap()),
-        While(condition, block)                     => While(condition.eval(), optimize(*block).wrap()),
+        MemberAssign(target, field, value)  => MemberAssign(target.eval(), field, value.eval()),
+        ClassDef(name, base, members)   => ClassDef(name, base, members
+                                                    .into_iter()
+                                                    .map(fold)
+                                                    .collect()),
+        VarDecl(typename, name, value)  => VarDecl(typename, name, value.map(|v| v.eval())),
+        FuncDef(return_type, name, tparams, params, body) => FuncDef(return_type, name, tparams, params, fold(*body).wrap()),
+        Return(expr)                                => Return(expr.map(|e| e.eval())),
+        If(condition, if_block, else_block)         => If(condition.eval(), fold(*if_block).wrap(), else_block.map(|b| fold(*b).wrap())),
+        For(init, condition, step, block)           => For(fold(*init).wrap(), condition.eval(), fold(*step).wrap(), fold(*block).wrap()),
+        While(condition, block)                     => While(condition.eval(), fold(*block).wrap()),
         Break                                       => Break,
         Continue                                    => Continue,
         FuncDecl { .. }                             => root,
+        Attributed(attributes, stmt)                => Attributed(attributes, fold(*stmt).wrap()),
         _ => {
             println!("Optimization is not supported for node {:#?}", root);
             root
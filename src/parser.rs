@@ -30,8 +30,10 @@ lazy_static::lazy_static! {
             .op(Op::infix(lt, Left) | Op::infix(lte, Left) | Op::infix(gt, Left) | Op::infix(gte, Left))
             .op(Op::infix(add, Left) | Op::infix(sub, Left))
             .op(Op::infix(mul, Left) | Op::infix(div, Left) | Op::infix(mmod, Left))
+            .op(Op::postfix(cast) | Op::postfix(is_test))
             .op(Op::prefix(inc) | Op::prefix(dec) | Op::prefix(neg) | Op::prefix(not))
             .op(Op::postfix(inc) | Op::postfix(dec))
+            .op(Op::postfix(member) | Op::postfix(member_call))
     };
 }
 
@@ -47,36 +49,63 @@ where
     Rule: Debug + Clone + Copy + Hash + Ord,
     Type: From<Pair<'a, Rule>>
 {
-    pair.into_inner().into_iter().map(|pair| pair.into()).collect()
+    pair.into_inner().map(|pair| pair.into()).collect()
 }
 
-fn pair_to_args_array<'a, Rule>(pair: Pair<'a, Rule>) -> Vec<(Type, String)>
-where
-    Rule: Debug + Clone + Copy + Hash + Ord,
-    Type: From<Pair<'a, Rule>>
-{
-    pair.into_inner().into_iter().map(|pair| {
+fn pair_to_args_array(pair: Pair<Rule>) -> Vec<(Type, String, Option<Expr>)> {
+    pair.into_inner().map(|pair| {
         let mut inner = pair.into_inner();
-        let ty = inner.next().unwrap().into();
+        let ty : Type = inner.expect(Rule::r#type);
+        let ty = match next_if(&mut inner, Rule::variadic) {
+            Some(_) => Type::Variadic(ty.wrap()),
+            None    => ty,
+        };
         let name = inner.next().unwrap().as_str().to_string();
-        (ty, name)
+        let default = inner.next().map(Into::into);
+        (ty, name, default)
     }).collect()
 }
 
 impl From<Pair<'_, Rule>> for Type {
     fn from(pair: Pair<'_, Rule>) -> Self {
-        let inner = pair.clone().into_inner().next().unwrap();
-        match inner.as_rule() {
+        if pair.as_rule() == Rule::r#type {
+            let mut members: Vec<Type> = pair.into_inner().map(Into::into).collect();
+
+            return match members.len() {
+                1 => members.remove(0),
+                _ => Type::Union(members),
+            };
+        }
+
+        let mut parts   = pair.clone().into_inner();
+        let inner       = parts.next().unwrap();
+        let base        = match inner.as_rule() {
             Rule::tnum          => Type::Number,
             Rule::tstr          => Type::String,
             Rule::tbool         => Type::Boolean,
             Rule::tvoid         => Type::Void,
             Rule::tobj          => Type::Object,
+            Rule::tgroup        => Type::Group,
+            Rule::tctrl         => Type::Control,
+            Rule::tany          => Type::Any,
             Rule::tarr          => Type::Array(Into::<Type>::into(inner).wrap()),
+            Rule::tfunc         => {
+                let mut parts   = inner.into_inner();
+                let params      = next_if(&mut parts, Rule::type_list).map(pair_to_type_array).unwrap_or_default();
+                let ret : Type  = parts.expect(Rule::r#type);
+
+                Type::Func(params, ret.wrap())
+            }
+            Rule::identifier    => Type::Class(pair_to_string(inner)),
             _ => {
                 println!("Bad type: {:?}", pair);
                 unreachable!()
             }
+        };
+
+        match next_if(&mut parts, Rule::toptional) {
+            Some(_) => Type::Optional(base.wrap()),
+            None    => base,
         }
     }
 }
@@ -93,10 +122,13 @@ impl From<Pair<'_, Rule>> for Stmt {
 
         match pair.as_rule() {
             Rule::var_decl => {
-                let tname   = inner.expect(Rule::r#type);
+                let tname   = match next_if(&mut inner, Rule::tauto) {
+                    Some(_) => Type::Auto,
+                    None    => inner.expect(Rule::r#type),
+                };
                 let name    = pair_to_string(inner.expect(Rule::identifier));
 
-                let value = if inner.peek() == None {
+                let value = if inner.peek().is_none() {
                     None
                 } else {
                     Some(inner.expect(Rule::expr))
@@ -107,9 +139,26 @@ impl From<Pair<'_, Rule>> for Stmt {
 
             Rule::assignment => {
                 let name    = pair_to_string(inner.expect(Rule::identifier));
+                let op      = inner.next().unwrap().as_rule();
+                let value   = inner.expect(Rule::expr);
+
+                Stmt::Assign(name.clone(), compound_assignment(op, Expr::Identifier(name), value))
+            }
+
+            Rule::member_assignment => {
+                let mut target  = Expr::Identifier(pair_to_string(inner.expect(Rule::identifier)));
+                let mut field   = pair_to_string(inner.expect(Rule::identifier));
+
+                while inner.peek().unwrap().as_rule() == Rule::identifier {
+                    target  = Expr::Member(target.wrap(), field);
+                    field   = pair_to_string(inner.expect(Rule::identifier));
+                }
+
+                let op      = inner.next().unwrap().as_rule();
                 let value   = inner.expect(Rule::expr);
+                let current = Expr::Member(target.clone().wrap(), field.clone());
 
-                Stmt::Assign(name, value)
+                Stmt::MemberAssign(target, field, compound_assignment(op, current, value))
             }
 
             Rule::expr => {
@@ -119,20 +168,58 @@ impl From<Pair<'_, Rule>> for Stmt {
             Rule::func_def => {
                 let rtype   = inner.expect(Rule::r#type);
                 let name    = pair_to_string(inner.expect(Rule::identifier));
-                let params  = pair_to_args_array(inner.expect(Rule::param_list));
+                let tparams = next_if(&mut inner, Rule::type_params).map(|pair| pair.into_inner().map(pair_to_string).collect()).unwrap_or_default();
+                let params  = next_if(&mut inner, Rule::param_list).map(pair_to_args_array).unwrap_or_default();
                 let body : Stmt    = inner.expect(Rule::compound_stmt);
 
-                Stmt::FuncDef(rtype, name, params, body.wrap())
+                Stmt::FuncDef(rtype, name, tparams, params, body.wrap())
             }
 
             Rule::func_decl => {
                 let rtype   = inner.expect(Rule::r#type);
                 let name    = pair_to_string(inner.expect(Rule::identifier));
-                let params  = pair_to_type_array(inner.expect(Rule::type_list));
+                let params  = next_if(&mut inner, Rule::type_list).map(pair_to_type_array).unwrap_or_default();
 
                 Stmt::FuncDecl(rtype, name, params)
             }
 
+            Rule::class_def => {
+                let name    = pair_to_string(inner.expect(Rule::identifier));
+                let base    = next_if(&mut inner, Rule::identifier).map(pair_to_string);
+                let members = inner.map(|member| member.into()).collect();
+
+                Stmt::ClassDef(name, base, members)
+            }
+
+            Rule::attributed | Rule::attributed_member => {
+                let mut attributes = Vec::new();
+
+                while let Some(attribute) = next_if(&mut inner, Rule::attribute) {
+                    let mut parts   = attribute.into_inner();
+                    let name        = pair_to_string(parts.next().unwrap());
+                    attributes.push((name, parts.map(pair_to_string).collect()));
+                }
+
+                let stmt : Stmt = inner.next().unwrap().into();
+                Stmt::Attributed(attributes, stmt.wrap())
+            }
+
+            // Constructors are methods named after their class that return an instance of it
+            Rule::ctor_def => {
+                let name    = pair_to_string(inner.expect(Rule::identifier));
+                let params  = next_if(&mut inner, Rule::param_list).map(pair_to_args_array).unwrap_or_default();
+                let body : Stmt    = inner.expect(Rule::compound_stmt);
+
+                Stmt::FuncDef(Type::Class(name.clone()), name, Vec::new(), params, body.wrap())
+            }
+
+            Rule::dtor_def => {
+                let name    = pair_to_string(inner.expect(Rule::identifier));
+                let body : Stmt    = inner.expect(Rule::compound_stmt);
+
+                Stmt::FuncDef(Type::Void, format!("~{}", name), Vec::new(), Vec::new(), body.wrap())
+            }
+
             // Rule::param_list => {
             //     let mut params = Vec::new();
 
@@ -148,7 +235,6 @@ impl From<Pair<'_, Rule>> for Stmt {
             // }
 
             Rule::compound_stmt => Stmt::Block(inner
-                                                    .into_iter()
                                                     .map(|param| param.into())
                                                     .collect()),
 
@@ -162,11 +248,11 @@ impl From<Pair<'_, Rule>> for Stmt {
             //                                         .map(|param| parse_expr(param.into_inner(), &PRATT_PARSER))
             //                                         .collect()),
 
-            Rule::return_stmt   => Stmt::Return(inner.expect(Rule::expr)),
+            Rule::return_stmt   => Stmt::Return(inner.next().map(Into::into)),
             Rule::if_stmt       => {
                 let cond        = inner.expect(Rule::expr);
                 let if_block    : Stmt = inner.expect(Rule::compound_stmt);
-                let else_block  : Option<Box<Stmt>> = if inner.peek() == None {
+                let else_block  : Option<Box<Stmt>> = if inner.peek().is_none() {
                     None
                 } else {
                     Some(<Pairs<'_, Rule> as Expectable<Rule, Stmt>>::expect(&mut inner, Rule::compound_stmt).wrap())
@@ -177,7 +263,8 @@ impl From<Pair<'_, Rule>> for Stmt {
             Rule::for_stmt      => {
                 let init    : Stmt = inner.expect(Rule::var_decl);
                 let cond    = inner.expect(Rule::expr);
-                let step    : Stmt = inner.expect(Rule::assignment);
+                // An assignment, or an expression like `i++`
+                let step    : Stmt = inner.next().unwrap().into();
                 let block   : Stmt = inner.expect(Rule::compound_stmt);
 
                 Stmt::For(init.wrap(), cond, step.wrap(), block.wrap())
@@ -198,6 +285,26 @@ impl From<Pair<'_, Rule>> for Stmt {
     }
 }
 
+fn next_if<'a>(pairs: &mut Pairs<'a, Rule>, rule: Rule) -> Option<Pair<'a, Rule>> {
+    match pairs.peek() {
+        Some(pair) if pair.as_rule() == rule => pairs.next(),
+        _ => None,
+    }
+}
+
+fn compound_assignment(op: Rule, target: Expr, value: Expr) -> Expr {
+    let op = match op {
+        Rule::add_assign    => Operator::Add,
+        Rule::sub_assign    => Operator::Sub,
+        Rule::mul_assign    => Operator::Mul,
+        Rule::div_assign    => Operator::Div,
+        Rule::mod_assign    => Operator::Mod,
+        _                   => return value,
+    };
+
+    Expr::BinaryOp(op, target.wrap(), value.wrap())
+}
+
 trait Expectable<I, O> {
     fn expect(&mut self, val: I) -> O;
 }
@@ -246,20 +353,42 @@ pub enum ParserError {
 
 impl From<ParserError> for io::Error {
     fn from(err: ParserError) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, format!("Parser error: {}", err))
+        io::Error::other(format!("Parser error: {}", err))
+    }
+}
+
+fn parse_arg(pair: Pair<Rule>) -> Expr {
+    match pair.as_rule() {
+        Rule::named_arg => {
+            let mut inner   = pair.into_inner();
+            let name        = pair_to_string(inner.expect(Rule::identifier));
+            let value: Expr = inner.expect(Rule::expr);
+
+            Expr::NamedArg(name, value.wrap())
+        }
+        _ => pair.into(),
     }
 }
 
 fn parse_func_call(mut pairs: Pairs<Rule>) -> Expr {
     let name : Pair<'_, _> = pairs.expect(Rule::identifier);
+    let args = pairs.map(parse_arg).collect();
 
-    let mut args = Vec::new();
+    Expr::FuncCall(name.as_str().to_string(), args)
+}
 
-    for mut pair in pairs {
-        args.push(pair.expect(Rule::expr));
-    }
+fn parse_new(mut pairs: Pairs<Rule>) -> Expr {
+    let class : Pair<'_, _> = pairs.expect(Rule::identifier);
+    let args = pairs.map(parse_arg).collect();
 
-    Expr::FuncCall(name.as_str().to_string(), args)
+    Expr::New(class.as_str().to_string(), args)
+}
+
+fn parse_member_call(target: Expr, mut pairs: Pairs<Rule>) -> Expr {
+    let method : Pair<'_, _> = pairs.expect(Rule::identifier);
+    let args = pairs.map(parse_arg).collect();
+
+    Expr::MethodCall(target.wrap(), method.as_str().to_string(), args)
 }
 
 fn parse_array_init(pairs: Pairs<Rule>) -> Expr {
@@ -289,9 +418,11 @@ fn parse_expr(pairs: Pairs<Rule>, pratt: &PrattParser<Rule>) -> Expr {
             Rule::bfalse        => Bool(false),
             Rule::number        => Number(primary.as_str().parse().unwrap()),
             Rule::string        => String(primary.as_str().to_string().replace("\"", "")),
+            Rule::null          => Null(None),
             Rule::identifier    => Identifier(primary.as_str().to_string()),
             Rule::expr          => parse_expr(primary.into_inner(), pratt), // from "(" ~ expr ~ ")"
             Rule::func_call     => parse_func_call(primary.into_inner()),
+            Rule::new_expr      => parse_new(primary.into_inner()),
             Rule::array_access  => parse_array_access(primary.into_inner()),
             Rule::array_init    => parse_array_init(primary.into_inner()),
             _                   => unreachable!(),
@@ -308,6 +439,10 @@ fn parse_expr(pairs: Pairs<Rule>, pratt: &PrattParser<Rule>) -> Expr {
         .map_postfix(|lhs, op| match op.as_rule() {
             Rule::inc           => UnaryOp(Inc, lhs.wrap(), true),
             Rule::dec           => UnaryOp(Dec, lhs.wrap(), true),
+            Rule::member        => Member(lhs.wrap(), pair_to_string(op.into_inner().next().unwrap())),
+            Rule::member_call   => parse_member_call(lhs, op.into_inner()),
+            Rule::cast          => Cast(lhs.wrap(), op.into_inner().expect(Rule::r#type)),
+            Rule::is_test       => Is(lhs.wrap(), op.into_inner().expect(Rule::r#type)),
             _                   => unreachable!(),
         })
 
@@ -0,0 +1,112 @@
+use crate::dgen_ast::*;
+use crate::boxable::Boxable;
+
+/// How a builtin is spelled in SQF
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Nular,
+    Unary,
+    Binary,
+}
+
+/// What running a builtin depends on and changes, which decides what the optimizer may do with its calls
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    // The result only depends on the arguments
+    Pure,
+    // Reads the game state or a random source, so calls can give different results, but changes nothing
+    Reads,
+    // Does something besides returning a value
+    Writes,
+}
+
+#[derive(Debug, Clone)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub command: &'static str,
+    pub kind: Command,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+    pub effect: Effect,
+}
+
+impl Builtin {
+    fn nular(name: &'static str, return_type: Type) -> Self {
+        Self { name, command: name, kind: Command::Nular, params: Vec::new(), return_type, effect: Effect::Pure }
+    }
+
+    fn unary(name: &'static str, param: Type, return_type: Type) -> Self {
+        Self { name, command: name, kind: Command::Unary, params: vec![param], return_type, effect: Effect::Pure }
+    }
+
+    fn binary(name: &'static str, left: Type, right: Type, return_type: Type) -> Self {
+        Self { name, command: name, kind: Command::Binary, params: vec![left, right], return_type, effect: Effect::Pure }
+    }
+
+    fn reads(self) -> Self {
+        Self { effect: Effect::Reads, ..self }
+    }
+
+    fn writes(self) -> Self {
+        Self { effect: Effect::Writes, ..self }
+    }
+
+    // Unary commands that take all their arguments packed into one array, like `format ["%1", x]`
+    fn variadic(name: &'static str, params: Vec<Type>, element: Type, return_type: Type) -> Self {
+        let params = params.into_iter().chain(std::iter::once(Type::Variadic(element.wrap()))).collect();
+        Self { name, command: name, kind: Command::Unary, params, return_type, effect: Effect::Pure }
+    }
+
+    pub fn is_variadic(&self) -> bool {
+        matches!(self.params.last(), Some(Type::Variadic(_)))
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref BUILTINS: Vec<Builtin> = {
+        use Type::*;
+
+        vec![
+            Builtin::nular("player",        Object).reads(),
+            Builtin::nular("time",          Number).reads(),
+
+            Builtin::unary("getPos",        Object,                 Array(Number.wrap())).reads(),
+            Builtin::unary("alive",         Object,                 Boolean).reads(),
+            Builtin::unary("damage",        Object,                 Number).reads(),
+            Builtin::unary("count",         Array(Any.wrap()),      Number),
+            Builtin::unary("hint",          String,                 Void).writes(),
+            Builtin::unary("systemChat",    String,                 Void).writes(),
+            Builtin::unary("diag_log",      Any,                    Void).writes(),
+            Builtin::unary("random",        Number,                 Number).reads(),
+            Builtin::unary("abs",           Number,                 Number),
+            Builtin::unary("floor",         Number,                 Number),
+            Builtin::unary("str",           Any,                    String),
+            Builtin::unary("parseNumber",   String,                 Number),
+            Builtin::unary("toArray",       String,                 Array(Number.wrap())),
+            Builtin::unary("toString",      Array(Number.wrap()),   String),
+
+            Builtin::binary("distance",     Object, Object,         Number).reads(),
+
+            Builtin::variadic("format",     vec![String], Any,      String),
+        ]
+    };
+}
+
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// Returns the builtin an `as` cast between two types goes through.
+///
+/// Anything printable converts to a string with `str` and strings parse back with `parseNumber`.
+/// `toArray`/`toString` work on character codes rather than values, so they are only reachable
+/// by calling them directly.
+pub fn conversion(from: &Type, to: &Type) -> Option<&'static str> {
+    use Type::*;
+
+    match (from, to) {
+        (Number | Boolean | Object | Group | Control | Array(_), String) => Some("str"),
+        (String, Number) => Some("parseNumber"),
+        _ => None,
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::generic::*;
+use crate::dgen_ast::*;
+use crate::boxable::Boxable;
+use crate::control_flow;
+use crate::prelude::{self, Effect};
+
+// What is known about variables at a point of the program: the literal they hold, or another variable holding the same value
+type Facts = HashMap<String, Expr>;
+
+/// Replaces reads of variables whose value is known with that value, a literal or another variable
+/// holding the same one, and folds what that makes constant. Values are forgotten when the variable
+/// is assigned, at the head of loops that assign it and after calls to functions that assign it.
+///
+/// Expects names resolved by the semantic analyzer, which renames variables that shadow another of
+/// the same function, so a name means a single variable within a function body.
+pub fn propagate(program: Stmt) -> Stmt {
+    let Stmt::Program(stmts) = program else { return program };
+    let propagation = Propagation { escaping: escaping_names(&stmts) };
+    let mut facts   = Facts::new();
+
+    Stmt::Program(stmts.into_iter().map(|stmt| propagation.stmt(stmt, &mut facts)).collect())
+}
+
+struct Propagation {
+    // Variables some function assigns without declaring them, which any call to user code may change
+    escaping: HashSet<String>,
+}
+
+impl Propagation {
+    fn stmt(&self, stmt: Stmt, facts: &mut Facts) -> Stmt {
+        use Stmt::*;
+
+        match stmt {
+            Expr(expr)                          => Expr(self.expr(expr, facts)),
+            VarDecl(typename, name, value)      => {
+                let value = value.map(|value| self.expr(value, facts));
+                assign(facts, &name, value.as_ref());
+                VarDecl(typename, name, value)
+            },
+            ArrayDecl(typename, name, value)    => {
+                let value = value.map(|value| self.expr(value, facts));
+                kill(facts, &name);
+                ArrayDecl(typename, name, value)
+            },
+            Assign(name, value)                 => {
+                let value = self.expr(value, facts);
+                assign(facts, &name, Some(&value));
+                Assign(name, value)
+            },
+            MemberAssign(target, field, value)  => MemberAssign(self.expr(target, facts), field, self.expr(value, facts)),
+            Return(value)                       => Return(value.map(|value| self.expr(value, facts))),
+            Block(stmts)                        => {
+                let block = Block(stmts.into_iter().map(|stmt| self.stmt(stmt, facts)).collect());
+
+                // The block's own variables go out of scope, along with the copies of them
+                for name in declared_in(&block) {
+                    kill(facts, &name);
+                }
+                block
+            },
+            If(cond, if_block, else_block)      => {
+                let cond            = self.expr(cond, facts);
+                let mut if_facts    = facts.clone();
+                let mut else_facts  = facts.clone();
+                let if_block        = self.stmt(*if_block, &mut if_facts);
+                let else_block      = else_block.map(|else_block| self.stmt(*else_block, &mut else_facts));
+
+                // A branch that never completes doesn't reach the code after the `if`
+                *facts = match (control_flow::completes(&if_block), else_block.as_ref().is_none_or(control_flow::completes)) {
+                    (true, false)   => if_facts,
+                    (false, true)   => else_facts,
+                    _               => common(if_facts, else_facts),
+                };
+                If(cond, if_block.wrap(), else_block.map(Boxable::wrap))
+            },
+            While(cond, block)                  => {
+                self.enter_loop(facts, &[&Stmt::Expr(cond.clone()), &block]);
+                let cond    = self.expr(cond, facts);
+                let block   = self.stmt(*block, &mut facts.clone());
+
+                While(cond, block.wrap())
+            },
+            For(init, cond, step, block)        => {
+                let init    = self.stmt(*init, facts);
+                self.enter_loop(facts, &[&Stmt::Expr(cond.clone()), &step, &block]);
+                let cond    = self.expr(cond, facts);
+                let block   = self.stmt(*block, &mut facts.clone());
+                let step    = self.stmt(*step, &mut facts.clone());
+
+                // The loop variable is only in scope in the loop
+                if let VarDecl(_, name, _) = &init {
+                    kill(facts, name);
+                }
+                For(init.wrap(), cond, step.wrap(), block.wrap())
+            },
+            // Nothing is known about the variables a function body starts with
+            FuncDef(return_type, name, type_params, params, body) => FuncDef(return_type, name, type_params, params, self.stmt(*body, &mut Facts::new()).wrap()),
+            ClassDef(name, base, members)       => ClassDef(name, base, members.into_iter().map(|member| match member.unattributed() {
+                Stmt::FuncDef(..)   => self.stmt(member, &mut Facts::new()),
+                _                   => member,
+            }).collect()),
+            Attributed(attributes, stmt)        => Attributed(attributes, self.stmt(*stmt, facts).wrap()),
+            other                               => other,
+        }
+    }
+
+    // The expression with the known values put in and folded; what it changes is forgotten after it
+    fn expr(&self, expr: Expr, facts: &mut Facts) -> Expr {
+        let changed = self.changed_by(&expr);
+
+        // Nothing the expression changes is put in, whichever operand SQF evaluates first
+        let known: Facts = facts.iter()
+            .filter(|(name, value)| !changed.contains(*name) && !reads_any(value, &changed))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        for name in &changed {
+            kill(facts, name);
+        }
+        substitute(expr, &known).eval()
+    }
+
+    // Only what holds on every iteration is known at the head of a loop
+    fn enter_loop(&self, facts: &mut Facts, parts: &[&Stmt]) {
+        let mut changed = HashSet::new();
+
+        for part in parts {
+            part.walk(&mut |stmt| {
+                match stmt {
+                    Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) | Stmt::Assign(name, _) => { changed.insert(name.clone()); },
+                    _ => (),
+                }
+                for expr in stmt.exprs() {
+                    changed.extend(self.changed_by(expr));
+                }
+            });
+        }
+
+        for name in &changed {
+            kill(facts, name);
+        }
+    }
+
+    // Variables evaluating the expression may assign
+    fn changed_by(&self, expr: &Expr) -> HashSet<String> {
+        let mut changed = stepped_names(expr);
+
+        if calls_user_code(expr) {
+            changed.extend(self.escaping.iter().cloned());
+        }
+        changed
+    }
+}
+
+// Records the value a variable is given when it's a literal or a copy of another variable; a nular
+// builtin like `time` may have changed by the time the copy is read
+fn assign(facts: &mut Facts, name: &str, value: Option<&Expr>) {
+    kill(facts, name);
+
+    match value {
+        Some(value @ (Expr::Number(_) | Expr::Bool(_) | Expr::String(_)))  => { facts.insert(name.to_string(), value.clone()); },
+        Some(Expr::Identifier(source)) if source != name && prelude::find(source).is_none_or(|builtin| builtin.effect == Effect::Pure) => {
+            facts.insert(name.to_string(), Expr::Identifier(source.clone()));
+        },
+        _                                                                   => (),
+    }
+}
+
+// Forgets the variable's value and the copies of it
+fn kill(facts: &mut Facts, name: &str) {
+    facts.retain(|variable, value| variable != name && !matches!(value, Expr::Identifier(source) if source == name));
+}
+
+// The facts both paths agree on
+fn common(left: Facts, right: Facts) -> Facts {
+    left.into_iter().filter(|(name, value)| right.get(name) == Some(value)).collect()
+}
+
+fn reads_any(value: &Expr, names: &HashSet<String>) -> bool {
+    matches!(value, Expr::Identifier(source) if names.contains(source))
+}
+
+fn substitute(expr: Expr, facts: &Facts) -> Expr {
+    match expr {
+        Expr::Identifier(name)                  => facts.get(&name).cloned().unwrap_or(Expr::Identifier(name)),
+        // Only a copy can stand in for an array, the access needs a variable
+        Expr::ArrayAccess(name, index)          => match facts.get(&name) {
+            Some(Expr::Identifier(source))      => Expr::ArrayAccess(source.clone(), substitute(*index, facts).wrap()),
+            _                                   => Expr::ArrayAccess(name, substitute(*index, facts).wrap()),
+        },
+        // `++` and `--` assign their operand, it stays a variable
+        Expr::UnaryOp(Operator::Inc | Operator::Dec, ..) => expr,
+        other                                   => other.map_children(&mut |child| substitute(child, facts)),
+    }
+}
+
+/// Variables `++` and `--` assign in the expression.
+pub fn stepped_names(expr: &Expr) -> HashSet<String> {
+    let mut names = HashSet::new();
+
+    expr.walk(&mut |expr| if let Expr::UnaryOp(Operator::Inc | Operator::Dec, operand, _) = expr {
+        if let Expr::Identifier(name) = &**operand {
+            names.insert(name.clone());
+        }
+    });
+
+    names
+}
+
+/// Whether the expression calls user functions, methods or constructors, which can assign the variables they see.
+pub fn calls_user_code(expr: &Expr) -> bool {
+    let mut calls = false;
+
+    expr.walk(&mut |expr| calls |= match expr {
+        Expr::FuncCall(name, _)             => prelude::find(name).is_none(),
+        Expr::New(..) | Expr::MethodCall(..) => true,
+        _                                   => false,
+    });
+
+    calls
+}
+
+// Variables the block itself declares, not counting nested blocks
+fn declared_in(block: &Stmt) -> Vec<String> {
+    block.children().into_iter().filter_map(|stmt| match stmt.unattributed() {
+        Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) => Some(name.clone()),
+        _ => None,
+    }).collect()
+}
+
+/// Variables functions assign that are neither their parameters nor declared in their body.
+pub fn escaping_names(program: &[Stmt]) -> HashSet<String> {
+    let mut escaping = HashSet::new();
+
+    for stmt in program {
+        stmt.walk(&mut |stmt| if let Stmt::FuncDef(_, _, _, params, body) = stmt {
+            let mut declared: HashSet<String> = params.iter().map(|(_, name, _)| name.clone()).collect();
+            let mut assigned = HashSet::new();
+
+            body.walk_local(&mut |stmt| {
+                match stmt {
+                    Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) => { declared.insert(name.clone()); },
+                    Stmt::Assign(name, _) => { assigned.insert(name.clone()); },
+                    _ => (),
+                }
+                for expr in stmt.exprs() {
+                    assigned.extend(stepped_names(expr));
+                }
+            });
+
+            escaping.extend(assigned.into_iter().filter(|name| !declared.contains(name)));
+        });
+    }
+
+    escaping
+}
@@ -0,0 +1,386 @@
+use std::collections::HashSet;
+
+use crate::generic::*;
+use crate::dgen_ast::*;
+use crate::boxable::Boxable;
+use crate::control_flow;
+use crate::inlining::all_names;
+use crate::lint::{Lint, LintConfig, LintLevels};
+use crate::optimizer::{constant_number, is_pure, Pass};
+use crate::propagation::stepped_names;
+
+/// Turns calls a function makes to itself as the last thing it does into a jump back to its start:
+/// the body runs in a `while (true)` loop and a tail call assigns the arguments to the parameters and
+/// `continue`s. SQF pays for every `call` and runs out of stack on deep recursion, a loop does neither.
+///
+/// A number function returning `e * f(...)` or `e + f(...)`, like a factorial, gets there by keeping
+/// the product or sum of the `e`s so far in a variable that every `return` then applies to its value.
+/// Tail calls inside the function's own loops stay calls, `continue` would mean those loops there.
+pub fn eliminate_tail_calls(program: Stmt) -> Stmt {
+    let Stmt::Program(stmts) = program else { return program };
+    let mut taken = all_names(&stmts);
+
+    Stmt::Program(stmts.into_iter().map(|stmt| loop_function(stmt, &mut taken)).collect())
+}
+
+// The function with its tail calls turned into jumps, or as it was if it makes none
+fn loop_function(function: Stmt, taken: &mut HashSet<String>) -> Stmt {
+    if let Stmt::Attributed(attributes, function) = function {
+        return Stmt::Attributed(attributes, loop_function(*function, taken).wrap());
+    }
+    let Stmt::FuncDef(return_type, name, type_params, params, body) = function else { return function };
+    let Stmt::Block(stmts) = *body else { return Stmt::FuncDef(return_type, name, type_params, params, body) };
+
+    // The rest of the arguments arrive packed into an array, they can't be assigned back one by one
+    if params.iter().any(|(typename, _, _)| matches!(typename, Type::Variadic(_))) {
+        return Stmt::FuncDef(return_type, name, type_params, params, Stmt::Block(stmts).wrap());
+    }
+
+    let mut tail_calls = TailCalls {
+        function:       &name,
+        params:         params.iter().map(|(typename, param, _)| (typename.clone(), param.clone())).collect(),
+        locals:         locals(&params, &stmts),
+        accumulator:    None,
+        taken,
+        jumps:          0,
+    };
+    if let Some(op) = tail_calls.accumulated(&stmts).filter(|_| return_type == Type::Number) {
+        let accumulator         = tail_calls.fresh(if op == Operator::Mul { "product" } else { "sum" });
+        tail_calls.accumulator  = Some((op, accumulator));
+    }
+
+    let mut looped      = tail_calls.block(stmts.clone(), return_type == Type::Void);
+    let accumulator     = tail_calls.accumulator.take();
+
+    if tail_calls.jumps == 0 {
+        return Stmt::FuncDef(return_type, name, type_params, params, Stmt::Block(stmts).wrap());
+    }
+
+    // Running off the end of a void function returns, not starts over
+    if control_flow::completes(&Stmt::Block(looped.clone())) {
+        looped.push(Stmt::Return(None));
+    }
+    // The loop starts over by itself at the end of its body
+    if looped.last() == Some(&Stmt::Continue) {
+        looped.pop();
+    }
+
+    let looped = Stmt::While(Expr::Bool(true), Stmt::Block(looped).wrap());
+    let body = match accumulator {
+        Some((op, accumulator)) => {
+            let identity = Expr::Number(if op == Operator::Mul { 1.0 } else { 0.0 });
+            vec![Stmt::VarDecl(Type::Number, accumulator.clone(), Some(identity)), accumulate_returns(looped, op, &accumulator)]
+        },
+        None => vec![looped],
+    };
+    Stmt::FuncDef(return_type, name, type_params, params, Stmt::Block(body).wrap())
+}
+
+struct TailCalls<'a> {
+    function: &'a str,
+    params: Vec<(Type, String)>,
+    // Parameters and variables the function declares, what an accumulated factor may read
+    locals: HashSet<String>,
+    // The operator of the `return e op f(...)`s the function makes and the variable combining their `e`s
+    accumulator: Option<(Operator, String)>,
+    // Names in use anywhere in the program, so the variables holding the next arguments can't clash with them
+    taken: &'a mut HashSet<String>,
+    jumps: usize,
+}
+
+impl TailCalls<'_> {
+    // The statements with their tail calls turned into jumps; `tail` when the function returns right after them
+    fn block(&mut self, stmts: Vec<Stmt>, tail: bool) -> Vec<Stmt> {
+        let mut result  = Vec::new();
+        let mut stmts   = stmts.into_iter().peekable();
+
+        while let Some(stmt) = stmts.next() {
+            let returns_next = matches!(stmts.peek(), Some(Stmt::Return(None)));
+            let in_tail      = returns_next || (tail && stmts.peek().is_none());
+
+            match stmt {
+                Stmt::Return(Some(Expr::FuncCall(name, args))) if self.is_self_call(&name, &args) => result.extend(self.jump(None, args)),
+                Stmt::Return(Some(value)) if self.accumulates(&value) => {
+                    let Expr::BinaryOp(_, left, right) = value else { unreachable!() };
+                    let (factor, call) = match (*left, *right) {
+                        (Expr::FuncCall(name, args), factor) if self.is_self_call(&name, &args) => (factor, args),
+                        (factor, Expr::FuncCall(_, args))   => (factor, args),
+                        _                                   => unreachable!(),
+                    };
+                    result.extend(self.jump(Some(factor), call));
+                },
+                Stmt::Expr(Expr::FuncCall(name, args)) if in_tail && self.is_self_call(&name, &args) => {
+                    result.extend(self.jump(None, args));
+                    // The `return` after the call can't be reached anymore
+                    if returns_next {
+                        stmts.next();
+                    }
+                },
+                Stmt::Block(block)                  => result.push(Stmt::Block(self.block(block, in_tail))),
+                Stmt::If(cond, if_block, else_block) => {
+                    let if_block    = self.branch(*if_block, in_tail);
+                    let else_block  = else_block.map(|else_block| self.branch(*else_block, in_tail).wrap());
+                    result.push(Stmt::If(cond, if_block.wrap(), else_block));
+                },
+                other                               => result.push(other),
+            }
+        }
+
+        result
+    }
+
+    fn branch(&mut self, stmt: Stmt, tail: bool) -> Stmt {
+        match stmt {
+            Stmt::Block(stmts)  => Stmt::Block(self.block(stmts, tail)),
+            other               => other,
+        }
+    }
+
+    fn is_self_call(&self, name: &str, args: &[Expr]) -> bool {
+        name == self.function && args.len() == self.params.len() && !args.iter().any(|arg| matches!(arg, Expr::NamedArg(..)))
+    }
+
+    // The operator of the first `return e * f(...)` or `return e + f(...)` a jump can replace
+    fn accumulated(&self, stmts: &[Stmt]) -> Option<Operator> {
+        stmts.iter().find_map(|stmt| match stmt {
+            Stmt::Return(Some(Expr::BinaryOp(op @ (Operator::Mul | Operator::Add), left, right))) => {
+                self.splits(left, right).then_some(op.clone())
+            },
+            Stmt::Block(stmts)                  => self.accumulated(stmts),
+            Stmt::If(_, if_block, else_block)   => std::iter::once(&**if_block).chain(else_block.as_deref()).find_map(|block| match block {
+                Stmt::Block(stmts)  => self.accumulated(stmts),
+                _                   => None,
+            }),
+            _ => None,
+        })
+    }
+
+    fn accumulates(&self, value: &Expr) -> bool {
+        match (value, &self.accumulator) {
+            (Expr::BinaryOp(op, left, right), Some((accumulated, _))) => op == accumulated && self.splits(left, right),
+            _ => false,
+        }
+    }
+
+    // Whether one side is a call to the function and the other a factor that can be computed before it:
+    // it changes nothing and only reads what the function itself declares, which the call can't change
+    fn splits(&self, left: &Expr, right: &Expr) -> bool {
+        let computable = |factor: &Expr| {
+            let mut local = is_pure(factor);
+            factor.walk(&mut |expr| local &= match expr {
+                Expr::Identifier(name) | Expr::ArrayAccess(name, _) => self.locals.contains(name),
+                Expr::Member(..)                                    => false,
+                _                                                   => true,
+            });
+            local
+        };
+
+        match (left, right) {
+            (Expr::FuncCall(name, args), factor) | (factor, Expr::FuncCall(name, args)) if self.is_self_call(name, args) => computable(factor),
+            _ => false,
+        }
+    }
+
+    // The parameters set to the arguments, as if the call had passed them, then back to the start; the
+    // factor the call's result was combined with goes into the accumulator first
+    fn jump(&mut self, factor: Option<Expr>, args: Vec<Expr>) -> Vec<Stmt> {
+        self.jumps += 1;
+
+        let params      = self.params.clone();
+        // Only a parameter can change what a later argument gives, calls can't see them
+        let ordered     = args.iter().any(|arg| !stepped_names(arg).is_empty());
+        let mut temps   = Vec::new();
+        let mut assigns = Vec::new();
+
+        if let (Some(factor), Some((op, accumulator))) = (factor, &self.accumulator) {
+            let accumulated = Expr::BinaryOp(op.clone(), Expr::Identifier(accumulator.clone()).wrap(), factor.wrap());
+            temps.push(Stmt::Assign(accumulator.clone(), accumulated));
+        }
+
+        for (index, ((typename, param), arg)) in params.iter().zip(args).enumerate() {
+            if arg == Expr::Identifier(param.clone()) {
+                continue;
+            }
+
+            // An argument reading a parameter assigned before it must see the old value, and when one
+            // steps a parameter all of them are evaluated before any parameter changes, in order
+            if ordered || params[..index].iter().any(|(_, earlier)| reads(&arg, earlier)) {
+                let temp = self.fresh(param);
+                temps.push(Stmt::VarDecl(typename.clone(), temp.clone(), Some(arg)));
+                assigns.push(Stmt::Assign(param.clone(), Expr::Identifier(temp)));
+            } else {
+                assigns.push(Stmt::Assign(param.clone(), arg));
+            }
+        }
+
+        temps.into_iter().chain(assigns).chain([Stmt::Continue]).collect()
+    }
+
+    fn fresh(&mut self, name: &str) -> String {
+        let fresh = (1..).map(|n| format!("{}_{}", name, n)).find(|fresh| !self.taken.contains(fresh)).unwrap();
+        self.taken.insert(fresh.clone());
+        fresh
+    }
+}
+
+fn locals(params: &[(Type, String, Option<Expr>)], body: &[Stmt]) -> HashSet<String> {
+    let mut locals: HashSet<String> = params.iter().map(|(_, name, _)| name.clone()).collect();
+
+    for stmt in body {
+        stmt.walk_local(&mut |stmt| if let Stmt::VarDecl(_, name, _) | Stmt::ArrayDecl(_, name, _) = stmt {
+            locals.insert(name.clone());
+        });
+    }
+
+    locals
+}
+
+// Every value the function still returns combined with what the jumps before it accumulated
+fn accumulate_returns(stmt: Stmt, op: Operator, accumulator: &str) -> Stmt {
+    let nested = |stmt: Box<Stmt>| accumulate_returns(*stmt, op.clone(), accumulator).wrap();
+
+    match stmt {
+        Stmt::Return(Some(value))           => Stmt::Return(Some(Expr::BinaryOp(op, Expr::Identifier(accumulator.to_string()).wrap(), value.wrap()))),
+        Stmt::Block(stmts)                  => Stmt::Block(stmts.into_iter().map(|stmt| accumulate_returns(stmt, op.clone(), accumulator)).collect()),
+        Stmt::If(cond, if_block, else_block) => Stmt::If(cond, nested(if_block), else_block.map(nested)),
+        Stmt::While(cond, block)            => Stmt::While(cond, nested(block)),
+        Stmt::For(init, cond, step, block)  => Stmt::For(init, cond, step, nested(block)),
+        Stmt::Attributed(attributes, stmt)  => Stmt::Attributed(attributes, nested(stmt)),
+        other                               => other,
+    }
+}
+
+fn reads(expr: &Expr, name: &str) -> bool {
+    let mut reads = false;
+
+    expr.walk(&mut |expr| reads |= matches!(expr, Expr::Identifier(n) | Expr::ArrayAccess(n, _) if n == name));
+
+    reads
+}
+
+// A function that still calls itself once its tail calls are loops, with how its argument counts towards the end
+struct Recursive {
+    name: String,
+    // The parameter that steps towards the base case, what the recursive call adds to it and the value it stops at
+    counter: Option<(usize, f64, f64)>,
+}
+
+/// Warns about calls to functions that recurse more levels deep than the configured limit, estimated
+/// from constant arguments to a function that steps a parameter by a constant on each call until it
+/// reaches a value its body compares it with. Tail calls only count as loops when `passes` turn them
+/// into loops.
+pub fn check(program: &Stmt, config: &LintConfig, passes: &[Pass]) -> (Vec<String>, Vec<String>) {
+    let Stmt::Program(stmts) = program else { return (Vec::new(), Vec::new()) };
+    let mut taken       = all_names(stmts);
+    let tail_calls      = passes.contains(&Pass::TailCall);
+    let reason          = if tail_calls { "only tail calls become loops" } else { "tail calls only become loops with the tailcall pass" };
+
+    let recursive: Vec<Recursive> = stmts.iter().filter_map(|stmt| {
+        let function = stmt.unattributed().clone();
+        let function = if tail_calls { loop_function(function, &mut taken) } else { function };
+
+        match function {
+            Stmt::FuncDef(_, name, _, params, body) if calls_itself(&name, &body) => Some(Recursive { counter: counter(&name, &params, &body), name }),
+            _ => None,
+        }
+    }).collect();
+
+    let mut lints       = LintLevels::new(config.clone());
+    let mut errors      = Vec::new();
+    let mut warnings    = Vec::new();
+
+    for stmt in stmts {
+        // Unknown attributes were already reported by the analyzer
+        if let Stmt::Attributed(attributes, _) = stmt {
+            lints.enter(attributes);
+        }
+
+        let caller = match stmt.unattributed() {
+            Stmt::FuncDef(_, name, ..)  => Some(name.as_str()),
+            _                           => None,
+        };
+
+        stmt.walk(&mut |stmt| for expr in stmt.exprs() {
+            expr.walk(&mut |expr| {
+                let Expr::FuncCall(name, args) = expr else { return };
+                let Some(function) = recursive.iter().find(|function| function.name == *name && caller != Some(name.as_str())) else { return };
+
+                if let Some(depth) = function.depth(args).filter(|depth| *depth > config.recursion_limit()) {
+                    let message = format!(
+                        "Call to '{}' recurses about {} levels deep, more than the limit of {}; {}",
+                        name, depth, config.recursion_limit(), reason
+                    );
+                    lints.report(Lint::DeepRecursion, &message, &mut errors, &mut warnings);
+                }
+            });
+        });
+
+        if let Stmt::Attributed(..) = stmt {
+            lints.exit();
+        }
+    }
+
+    (errors, warnings)
+}
+
+impl Recursive {
+    // Levels of calls a call with these arguments makes, when the argument the function counts with is known
+    fn depth(&self, args: &[Expr]) -> Option<usize> {
+        let (index, step, end) = self.counter?;
+        let start = constant_number(args.get(index)?)?;
+
+        match (end - start) / step {
+            steps if steps > 0.0    => Some(steps.ceil() as usize + 1),
+            _                       => Some(1),
+        }
+    }
+}
+
+fn calls_itself(name: &str, body: &Stmt) -> bool {
+    let mut calls = false;
+
+    body.walk_local(&mut |stmt| for expr in stmt.exprs() {
+        expr.walk(&mut |expr| calls |= matches!(expr, Expr::FuncCall(callee, _) if callee == name));
+    });
+
+    calls
+}
+
+// The parameter a recursive call passes back stepped by a constant, like `n - 1`, with the step and the
+// constant the body compares the parameter with to stop
+fn counter(name: &str, params: &[(Type, String, Option<Expr>)], body: &Stmt) -> Option<(usize, f64, f64)> {
+    let mut steps   = Vec::new();
+    let mut ends    = Vec::new();
+
+    body.walk_local(&mut |stmt| {
+        if let Stmt::If(cond, _, _) = stmt {
+            cond.walk(&mut |expr| if let Expr::BinaryOp(Operator::Eq | Operator::Lt | Operator::Lte | Operator::Gt | Operator::Gte, left, right) = expr {
+                match (&**left, &**right) {
+                    (Expr::Identifier(param), Expr::Number(end)) | (Expr::Number(end), Expr::Identifier(param)) => ends.push((param.clone(), *end)),
+                    _ => (),
+                }
+            });
+        }
+
+        for expr in stmt.exprs() {
+            expr.walk(&mut |expr| if let Expr::FuncCall(callee, args) = expr {
+                if callee == name {
+                    steps.extend(args.iter().enumerate().filter_map(|(index, arg)| match arg {
+                        Expr::BinaryOp(op @ (Operator::Sub | Operator::Add), left, right) => match (&**left, &**right) {
+                            (Expr::Identifier(param), Expr::Number(step)) if params.get(index).is_some_and(|(_, p, _)| p == param) && *step > 0.0 => {
+                                Some((index, if *op == Operator::Sub { -step } else { *step }))
+                            },
+                            _ => None,
+                        },
+                        _ => None,
+                    }));
+                }
+            });
+        }
+    });
+
+    steps.into_iter().find_map(|(index, step)| {
+        let end = ends.iter().find(|(param, _)| *param == params[index].1)?.1;
+        Some((index, step, end))
+    })
+}
@@ -1,66 +1,219 @@
-use std::clone;
+use std::collections::HashMap;
 
+use crate::generic::*;
 use crate::dgen_ast::*;
 use crate::boxable::Boxable;
+use crate::prelude::{self, Command, BUILTINS};
+use crate::control_flow::{self, ControlFlow};
+use crate::recursion;
+use crate::lint::{self, Level, Lint, LintConfig, LintLevels};
+use crate::dgen2sqf_ast::mangle;
+use crate::optimizer::{self, constant_condition, constant_number, Pass, PassManager};
+use crate::narrowing::{non_null_facts, type_facts};
+
+// Name and default value of each parameter of a function
+type Defaults = Vec<(String, Option<Expr>)>;
+
+// Arguments of a call in parameter order, after the types they are checked against
+type BoundArgs = (Vec<Type>, Vec<(Expr, Type)>);
 
 #[derive(Debug, Clone, PartialEq)]
 enum Symbol {
     Var { typename: Type, name: String },
     Array { typename: Type, name: String, size: usize },
-    Func { return_type: Type, name: String, params: Vec<Type> },
+    // `defaults` is empty when the parameter names aren't known
+    Func { return_type: Type, name: String, type_params: Vec<String>, params: Vec<Type>, defaults: Defaults },
+    Class { typename: Type, name: String, base: Option<String>, fields: Vec<(Type, String)>, methods: Vec<Symbol>, ctor: Option<(Vec<Type>, Defaults)> },
+    // A variable whose type a null check or an `is` test has refined
+    Narrowed { typename: Type, name: String, declared: Type },
 }
 
 #[derive(Debug, Clone)]
 struct Scope {
     symbols: Vec<Symbol>,
+    // Names of this scope's symbols that have been read
+    used: Vec<String>,
+    // Symbols to lint if they are never used, with the lint level in effect where they were declared
+    declared: Vec<(Lint, String, Level)>,
+    // Names given to this scope's variables in the output when they differ from the source
+    renames: Vec<(String, String)>,
+    // Whether this is the outermost scope of a function body
+    function: bool,
     parent: Option<Box<Scope>>,
 }
 
 #[derive(Debug)]
 pub struct SemanticAnalyzer {
     scope: Box<Scope>,
+    type_params: Vec<String>,
+    return_type: Type,
+    // The variadic parameter of the function being checked, passed on whole to a variadic parameter
+    rest_param: Option<String>,
+    // Variables declared without a value that are not assigned on every path reaching this point
+    unassigned: Vec<String>,
+    // Functions known from a prototype or the declaration pre-pass whose definition hasn't been checked yet
+    undefined: Vec<(String, Vec<Type>)>,
+    // Number of shadowing variables renamed so far, to keep their new names unique
+    renamed: usize,
+    lints: LintLevels,
+    // Passes the optimizer runs afterwards, which decide what recursion ends up a loop
+    passes: Vec<Pass>,
     errors: Vec<String>,
+    warnings: Vec<String>,
 }
 
 impl Symbol {
     pub fn typename(&self) -> &Type {
         match self {
-            Symbol::Var { typename, .. } | Symbol::Array { typename, .. } | Symbol::Func { return_type: typename, .. } => typename,
+            Symbol::Var { typename, .. } | Symbol::Array { typename, .. } | Symbol::Func { return_type: typename, .. } | Symbol::Class { typename, .. } | Symbol::Narrowed { typename, .. } => typename,
         }
     }
 
     pub fn name(&self) -> &str {
         match self {
-            Symbol::Var { name, .. } | Symbol::Array { name, .. } | Symbol::Func { name, .. } => name,
+            Symbol::Var { name, .. } | Symbol::Array { name, .. } | Symbol::Func { name, .. } | Symbol::Class { name, .. } | Symbol::Narrowed { name, .. } => name,
         }
     }
 
-    pub fn size(&self) -> Option<usize> {
+    // The type the variable was declared with, ignoring any narrowing
+    pub fn declared_type(&self) -> &Type {
         match self {
-            Symbol::Array { size, .. } => Some(*size),
-            _ => None,
+            Symbol::Narrowed { declared, .. } => declared,
+            _ => self.typename(),
         }
     }
 }
 
 impl Scope {
     pub fn new(parent: Option<Box<Scope>>) -> Self {
-        Self { symbols: Vec::new(), parent }
+        Self { symbols: Vec::new(), used: Vec::new(), declared: Vec::new(), renames: Vec::new(), function: false, parent }
     }
 
     pub fn define(&mut self, symbol: Symbol) {
         self.symbols.push(symbol);
     }
 
+    // Later symbols win, so a narrowing hides the declaration it refines
     pub fn find_symbol(&self, name: &str) -> Option<Symbol> {
-        self.symbols.iter().find(|symbol| symbol.name() == name).cloned()
+        self.symbols.iter().rev().find(|symbol| symbol.name() == name).cloned()
             .or_else(|| self.parent.as_ref()?.find_symbol(name))
     }
+
+    // Declared in this very scope; narrowings only refine a declaration from further out
+    pub fn find_local(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name() == name && !matches!(symbol, Symbol::Narrowed { .. }))
+    }
+
+    // Declared in this scope or an enclosing one of the same function
+    pub fn in_function(&self, name: &str) -> bool {
+        self.find_local(name).is_some() || (!self.function && self.parent.as_ref().is_some_and(|parent| parent.in_function(name)))
+    }
+
+    // Parameters and return types of the functions of that name declared in this very scope
+    pub fn local_overloads(&self, name: &str) -> Vec<(Vec<Type>, Type)> {
+        self.symbols.iter().filter_map(|symbol| match symbol {
+            Symbol::Func { name: n, params, return_type, .. } if n == name => Some((params.clone(), return_type.clone())),
+            _ => None,
+        }).collect()
+    }
+
+    // Every visible function of that name, outermost and earliest declared first
+    pub fn find_overloads(&self, name: &str) -> Vec<Symbol> {
+        let mut overloads = self.parent.as_ref().map_or(Vec::new(), |parent| parent.find_overloads(name));
+        overloads.extend(self.symbols.iter().filter(|symbol| matches!(symbol, Symbol::Func { name: n, .. } if n == name)).cloned());
+        overloads
+    }
+
+    // Swaps a declared overload for its definition, keeping its place among the others
+    pub fn replace_overload(&mut self, params: &[Type], symbol: Symbol) {
+        let name = symbol.name().to_string();
+
+        match self.symbols.iter_mut().find(|s| matches!(s, Symbol::Func { name: n, params: p, .. } if *n == name && p == params)) {
+            Some(declared) => *declared = symbol,
+            None => self.symbols.push(symbol),
+        }
+    }
+
+    // The first overload of a function keeps its name, the others are told apart by their parameter types
+    pub fn overload_name(&self, name: &str, params: &[Type]) -> String {
+        match self.find_overloads(name).first() {
+            Some(Symbol::Func { params: first, .. }) if first != params => mangle(name, params),
+            _ => name.to_string(),
+        }
+    }
+
+    // The name a variable is emitted under
+    pub fn resolve(&self, name: &str) -> String {
+        if self.find_local(name).is_some() {
+            return self.renames.iter().find(|(n, _)| n == name).map_or(name, |(_, renamed)| renamed).to_string();
+        }
+
+        self.parent.as_ref().map_or(name.to_string(), |parent| parent.resolve(name))
+    }
+
+    pub fn mark_used(&mut self, name: &str) {
+        if self.symbols.iter().any(|symbol| symbol.name() == name && !matches!(symbol, Symbol::Narrowed { .. })) {
+            self.used.push(name.to_string());
+        } else if let Some(parent) = self.parent.as_mut() {
+            parent.mark_used(name);
+        }
+    }
+
+    // What is known about the variables of enclosing scopes that some scope in the chain narrowed
+    pub fn narrowings(&self) -> Vec<Symbol> {
+        let mut names = Vec::new();
+        let mut scope = Some(self);
+
+        while let Some(current) = scope {
+            for symbol in &current.symbols {
+                if matches!(symbol, Symbol::Narrowed { .. }) && !names.contains(&symbol.name()) && self.find_local(symbol.name()).is_none() {
+                    names.push(symbol.name());
+                }
+            }
+            scope = current.parent.as_deref();
+        }
+
+        names.into_iter().filter_map(|name| self.find_symbol(name)).filter(|symbol| matches!(symbol, Symbol::Narrowed { .. })).collect()
+    }
+
+    pub fn forget_narrowing(&mut self, name: &str) {
+        self.symbols.retain(|symbol| !matches!(symbol, Symbol::Narrowed { name: n, .. } if n == name));
+
+        if let Some(parent) = self.parent.as_mut() {
+            parent.forget_narrowing(name);
+        }
+    }
+}
+
+impl Default for SemanticAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SemanticAnalyzer {
     pub fn new() -> Self {
-        Self { scope: Scope::new(None).wrap(), errors: Vec::new() }
+        Self::with_lints(LintConfig::default())
+    }
+
+    pub fn with_lints(config: LintConfig) -> Self {
+        let mut scope = Scope::new(None);
+
+        for builtin in BUILTINS.iter() {
+            let name = builtin.name.to_string();
+
+            scope.define(match builtin.kind {
+                Command::Nular  => Symbol::Var { typename: builtin.return_type.clone(), name },
+                _               => Symbol::Func { return_type: builtin.return_type.clone(), name, type_params: Vec::new(), params: builtin.params.clone(), defaults: Vec::new() },
+            });
+        }
+
+        Self { scope: scope.wrap(), type_params: Vec::new(), return_type: Type::Void, rest_param: None, unassigned: Vec::new(), undefined: Vec::new(), renamed: 0, lints: LintLevels::new(config), passes: PassManager::new(optimizer::Level::Full).passes().to_vec(), errors: Vec::new(), warnings: Vec::new() }
+    }
+
+    /// Sets the optimization passes that will run on the analyzed program, `-O2`'s unless told otherwise.
+    pub fn set_passes(&mut self, passes: &[Pass]) {
+        self.passes = passes.to_vec();
     }
 
     fn enter_scope(&mut self) {
@@ -68,6 +221,7 @@ impl SemanticAnalyzer {
     }
 
     fn exit_scope(&mut self) {
+        self.report_unused();
         self.scope = self.scope.parent.take().expect("No parent scope to exit to");
     }
 
@@ -75,183 +229,1534 @@ impl SemanticAnalyzer {
         self.errors.push(message.to_string());
     }
 
+    fn lint(&mut self, lint: Lint, message: &str) {
+        self.lints.report(lint, message, &mut self.errors, &mut self.warnings);
+    }
+
+    // Remembers a symbol of the current scope to lint if nothing reads it
+    fn declare(&mut self, lint: Lint, name: &str) {
+        let level = self.lints.level(lint);
+        self.scope.declared.push((lint, name.to_string(), level));
+    }
+
+    fn report_unused(&mut self) {
+        for (lint, name, level) in std::mem::take(&mut self.scope.declared) {
+            if !self.scope.used.contains(&name) {
+                let kind = match lint {
+                    Lint::UnusedFunction    => "function",
+                    Lint::UnusedParameter   => "parameter",
+                    _                       => "variable",
+                };
+                lint::report_at(level, lint, &format!("Unused {} '{}'", kind, name), &mut self.errors, &mut self.warnings);
+            }
+        }
+    }
+
     pub fn errors(&self) -> &[String] {
         &self.errors
     }
 
-    fn analyze_expr(&mut self, expr: Expr) -> Type {
-        match expr.clone() {
-            Expr::Identifier(name)                  => self.lookup_type(&name),
-            Expr::BinaryOp(_, left, right)          => self.check_binary_expr(*left, *right),
-            Expr::UnaryOp(_, expr, _)               => self.analyze_expr(*expr),
-            Expr::FuncCall(name, args)              => self.check_func_call(&name, args),
-            Expr::Bool(_)                           => Type::Boolean,
-            Expr::Number(_)                         => Type::Number,
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    fn analyze_expr(&mut self, expr: Expr) -> (Expr, Type) {
+        match expr {
+            Expr::Identifier(name)                  => {
+                self.check_assigned(&name);
+                let t = self.lookup_type(&name);
+                (Expr::Identifier(self.scope.resolve(&name)), t)
+            }
+            Expr::BinaryOp(op, left, right)         => self.check_binary_expr(op, *left, *right),
+            Expr::UnaryOp(op, operand, is_postfix)  => self.check_unary_expr(op, *operand, is_postfix),
+            Expr::FuncCall(name, args)              => self.check_func_call(name, args),
+            Expr::Bool(_)                           => (expr, Type::Boolean),
+            Expr::Number(_)                         => (expr, Type::Number),
+            Expr::String(_)                         => (expr, Type::String),
+            Expr::Null(_)                           => (expr, Type::Null),
             Expr::Array(elements)                   => self.check_array_init(elements),
             Expr::ArrayAccess(array, index)         => self.check_array_access(array, *index),
-            _ => Type::Any,
+            Expr::New(class, args)                  => self.check_new(class, args),
+            Expr::Member(target, field)             => self.check_member(*target, field),
+            Expr::MethodCall(target, method, args)  => self.check_method_call(*target, method, args),
+            Expr::Cast(value, typename)             => self.check_cast(*value, typename),
+            Expr::Is(value, typename)               => self.check_is(*value, typename),
+            Expr::NamedArg(name, value)             => {
+                self.add_error(&format!("Named argument '{}' outside of a call", name));
+                self.analyze_expr(*value)
+            }
+            Expr::Spread(value)                     => {
+                let (value, value_type) = self.analyze_expr(*value);
+                (Expr::Spread(value.wrap()), value_type)
+            }
         }
     }
 
     pub fn analyze(&mut self, stmt: Stmt) -> Stmt {
         match stmt.clone() {
             Stmt::Block(stmts)                      => {
+                let outer_unassigned = self.unassigned.clone();
+
                 self.enter_scope();
                 let analyzed = Stmt::Block(stmts.into_iter().map(|stmt| self.analyze(stmt)).collect());
+                // What the block found out about outer variables still holds once it ends
+                let narrowed = self.scope.narrowings();
                 self.exit_scope();
+                self.define_narrowings(narrowed);
+
+                // Variables declared inside the block are gone once it ends
+                self.unassigned.retain(|name| outer_unassigned.contains(name));
                 analyzed
             }
-            Stmt::Program(stmts)                    => Stmt::Program(stmts.into_iter().map(|stmt| self.analyze(stmt)).collect()),
-            Stmt::Expr(expr)                        => { self.analyze_expr(expr); stmt },
-            Stmt::Assign(name, value)               => self.check_assignment(name, value, stmt),
+            Stmt::Program(stmts)                    => {
+                self.collect_declarations(&stmts);
+
+                let program                 = Stmt::Program(stmts.into_iter().map(|stmt| self.analyze(stmt)).collect());
+                self.report_unused();
+
+                let (errors, warnings)      = ControlFlow::check(&program, self.lints.config());
+                self.errors.extend(errors);
+                self.warnings.extend(warnings);
+
+                let (errors, warnings)      = recursion::check(&program, self.lints.config(), &self.passes);
+                self.errors.extend(errors);
+                self.warnings.extend(warnings);
+                program
+            }
+            Stmt::Expr(expr)                        => Stmt::Expr(self.analyze_expr(expr).0),
+            Stmt::Assign(name, value)               => self.check_assignment(name, value),
+            Stmt::MemberAssign(target, field, value) => self.check_member_assignment(target, field, value),
+            Stmt::ClassDef(name, base, members)     => self.check_class_def(name, base, members, stmt),
             Stmt::VarDecl(t, name, value)           => self.check_var_decl(t, name, value, stmt),
             Stmt::FuncDecl(rtype, name, params)     => self.check_func_decl(rtype, name, params, stmt),
-            Stmt::FuncDef(rtype, name, params, body) => self.check_func_def(rtype, name, params, body, stmt),
-            Stmt::Return(expr)                      => { self.analyze_expr(expr); stmt },
-            Stmt::If(cond, ifb, elseb)              => {
-                self.analyze_expr(cond);
-                self.analyze(*ifb);
-                if let Some(elseb) = elseb { self.analyze(*elseb); }
-                stmt
+            Stmt::FuncDef(rtype, name, tparams, params, body) => self.check_func_def(rtype, name, tparams, params, *body, stmt),
+            Stmt::Return(Some(expr))                => self.check_return(expr),
+            Stmt::Attributed(attributes, stmt)      => {
+                for error in self.lints.enter(&attributes) {
+                    self.add_error(&error);
+                }
+                let exported = attributes.iter().any(|(name, _)| name == "export").then(|| match stmt.unattributed() {
+                    Stmt::FuncDef(_, name, ..) => Some(name.clone()),
+                    _ => None,
+                });
+                let stmt = self.analyze(*stmt);
+                self.lints.exit();
+
+                // Other scripts call exported functions, so they count as used
+                match exported {
+                    Some(Some(name))    => self.scope.mark_used(&name),
+                    Some(None)          => self.add_error("Only functions can be exported"),
+                    None                => (),
+                }
+
+                let has = |attribute: &str| attributes.iter().any(|(name, _)| name == attribute);
+
+                for attribute in ["inline", "noinline"].into_iter().filter(|attribute| has(attribute)) {
+                    if !matches!(stmt.unattributed(), Stmt::FuncDef(..)) {
+                        self.add_error(&format!("Attribute '{}' only applies to functions", attribute));
+                    }
+                }
+                if has("inline") && has("noinline") {
+                    self.add_error("A function can't be both 'inline' and 'noinline'");
+                }
+
+                Stmt::Attributed(attributes, stmt.wrap())
             }
+            Stmt::If(cond, ifb, elseb)              => self.check_if(cond, *ifb, elseb.map(|elseb| *elseb)),
             Stmt::For(init, cond, step, block)      => {
-                self.analyze(*init);
-                self.analyze_expr(cond);
-                self.analyze(*step);
-                self.analyze(*block);
-                stmt
+                // The loop variable belongs to the loop, not to the enclosing block
+                self.enter_scope();
+                let init        = self.analyze(*init);
+                self.forget_assigned(&step);
+                self.forget_assigned(&block);
+                let (cond, _)   = self.analyze_expr(cond);
+                self.check_loop(&cond, &block);
+
+                // The body may not run at all, so nothing it assigns counts afterwards
+                let unassigned  = self.unassigned.clone();
+                let block       = self.analyze(*block);
+                let step        = self.analyze(*step);
+                self.unassigned = unassigned;
+                self.exit_scope();
+
+                Stmt::For(init.wrap(), cond, step.wrap(), block.wrap())
+            }
+            Stmt::While(cond, block)                => {
+                self.forget_assigned(&block);
+                let (cond, _)   = self.analyze_expr(cond);
+                self.check_loop(&cond, &block);
+
+                // The body may not run at all, so neither what it assigns nor what it narrows counts afterwards
+                let unassigned  = self.unassigned.clone();
+                self.enter_scope();
+                let block       = self.analyze(*block);
+                self.exit_scope();
+                self.unassigned = unassigned;
+
+                Stmt::While(cond, block.wrap())
             }
-            Stmt::While(cond, block)                => { self.analyze_expr(cond); self.analyze(*block); stmt }
             _ => stmt,
         }
     }
 
+    // `while (true)` is how endless loops are spelled, so only a loop that never runs is suspicious
+    fn check_loop(&mut self, cond: &Expr, block: &Stmt) {
+        if constant_condition(cond) == Some(false) {
+            self.lint(Lint::ConstantCondition, "Loop condition is always false");
+        }
+
+        if is_empty_block(block) {
+            self.lint(Lint::EmptyBlock, "Empty loop body");
+        }
+    }
+
+    fn check_if(&mut self, cond: Expr, if_block: Stmt, else_block: Option<Stmt>) -> Stmt {
+        let (cond, _)   = self.analyze_expr(cond);
+        let unassigned  = self.unassigned.clone();
+
+        if let Some(value) = constant_condition(&cond) {
+            self.lint(Lint::ConstantCondition, &format!("Condition is always {}", value));
+        }
+
+        if is_empty_block(&if_block) {
+            self.lint(Lint::EmptyBlock, "Empty 'if' block");
+        }
+
+        if else_block.as_ref().is_some_and(is_empty_block) {
+            self.lint(Lint::EmptyBlock, "Empty 'else' block");
+        }
+
+        self.enter_scope();
+        self.narrow_by(&cond, true);
+        let if_block    = self.analyze(if_block);
+        let if_narrowed = self.scope.narrowings();
+        self.exit_scope();
+
+        let if_unassigned = std::mem::replace(&mut self.unassigned, unassigned);
+
+        // Without an `else`, control gets past the `if` with only the condition's failure known
+        self.enter_scope();
+        self.narrow_by(&cond, false);
+        let else_block      = else_block.map(|else_block| self.analyze(else_block));
+        let else_narrowed   = self.scope.narrowings();
+        self.exit_scope();
+
+        // `if (x == null) { return; }` leaves `x` checked for the rest of the block
+        let if_exits    = !control_flow::completes(&if_block);
+        let else_exits  = else_block.as_ref().is_some_and(|else_block| !control_flow::completes(else_block));
+
+        // A variable is assigned after the `if` when every branch that falls through assigned it
+        if else_exits {
+            self.unassigned = if_unassigned;
+        } else if !if_exits {
+            for name in if_unassigned {
+                if !self.unassigned.contains(&name) {
+                    self.unassigned.push(name);
+                }
+            }
+        }
+
+        // Past the `if`, what every branch that gets there found out holds
+        let narrowed = match (if_exits, else_exits) {
+            (false, false)  => if_narrowed.into_iter().filter(|symbol| else_narrowed.contains(symbol)).collect(),
+            (false, true)   => if_narrowed,
+            (true, false)   => else_narrowed,
+            (true, true)    => Vec::new(),
+        };
+        self.define_narrowings(narrowed);
+
+        Stmt::If(cond, if_block.wrap(), else_block.map(|else_block| else_block.wrap()))
+    }
+
+    // Whether a value may be returned at all is left to the control-flow pass
+    fn check_return(&mut self, value: Expr) -> Stmt {
+        let (value, value_type) = self.analyze_expr(value);
+        let return_type         = self.return_type.clone();
+
+        if return_type != Type::Void {
+            self.expect_assignable(&value_type, &return_type, format!("Return type mismatch: expected {:?}, got {:?}", return_type, value_type));
+        }
+
+        Stmt::Return(Some(coerce_null(value, &return_type)))
+    }
+
+    // Refines the variables `cond` says something about, for code that only runs when it is `outcome`
+    fn narrow_by(&mut self, cond: &Expr, outcome: bool) {
+        self.narrow(non_null_facts(cond, outcome));
+        self.narrow_types(type_facts(cond, outcome));
+    }
+
+    fn narrow(&mut self, names: Vec<String>) {
+        for name in names {
+            if let Some(Type::Optional(inner)) = self.scope.find_symbol(&name).map(|symbol| symbol.declared_type().clone()) {
+                self.scope.define(Symbol::Narrowed { typename: *inner.clone(), name, declared: Type::Optional(inner) });
+            }
+        }
+    }
+
+    // A passed `is` test makes the variable that type; a failed one rules the type out of its union
+    fn narrow_types(&mut self, facts: Vec<(String, Type, bool)>) {
+        for (name, tested, holds) in facts {
+            let Some(symbol @ (Symbol::Var { .. } | Symbol::Narrowed { .. })) = self.scope.find_symbol(&name) else { continue };
+
+            let narrowed = match (holds, symbol.typename()) {
+                (true, _)                       => Some(tested),
+                (false, Type::Union(members))   => union_without(members, &tested),
+                (false, Type::Optional(inner))  => match &**inner {
+                    Type::Union(members)    => union_without(members, &tested).map(|rest| Type::Optional(rest.wrap())),
+                    _                       => None,
+                },
+                _                               => None,
+            };
+
+            if let Some(narrowed) = narrowed.filter(|narrowed| narrowed != symbol.typename()) {
+                self.scope.define(Symbol::Narrowed { typename: narrowed, name, declared: symbol.declared_type().clone() });
+            }
+        }
+    }
+
+    fn define_narrowings(&mut self, narrowed: Vec<Symbol>) {
+        for symbol in narrowed {
+            if self.scope.find_symbol(symbol.name()).as_ref() != Some(&symbol) {
+                self.scope.define(symbol);
+            }
+        }
+    }
+
+    // Loop bodies run more than once, so whatever they reassign is unchecked again at the top
+    fn forget_assigned(&mut self, stmt: &Stmt) {
+        for name in assigned_names(stmt) {
+            self.scope.forget_narrowing(&name);
+        }
+    }
+
+    fn check_assigned(&mut self, name: &str) {
+        if let Some(index) = self.unassigned.iter().position(|unassigned| unassigned == name) {
+            self.add_error(&format!("'{}' may be used before it is assigned", name));
+            // Reported once, the rest of the reads would only repeat it
+            self.unassigned.remove(index);
+        }
+    }
+
     fn lookup_type(&mut self, name: &str) -> Type {
-        self.scope.find_symbol(name)
-            .map_or_else(|| {
+        self.scope.mark_used(name);
+
+        match self.scope.find_symbol(name) {
+            Some(Symbol::Func { .. }) if prelude::find(name).is_some() => {
+                self.add_error(&format!("Builtin '{}' cannot be used as a value", name));
+                Type::Unknown
+            }
+            Some(Symbol::Func { .. }) if self.scope.find_overloads(name).len() > 1 => {
+                self.add_error(&format!("Overloaded function '{}' cannot be used as a value", name));
+                Type::Unknown
+            }
+            Some(Symbol::Func { return_type, params, .. }) => Type::Func(params, return_type.wrap()),
+            Some(symbol) => symbol.typename().clone(),
+            None => {
                 self.add_error(&format!("Undefined variable: '{}'", name));
-                Type::Any
-            }, |symbol| symbol.typename().clone())
+                Type::Unknown
+            }
+        }
     }
 
-    fn check_binary_expr(&mut self, left: Expr, right: Expr) -> Type {
-        let left_type = self.analyze_expr(left.clone());
-        let right_type = self.analyze_expr(right.clone());
-        if left_type != right_type {
-            self.add_error(&format!("Binary expression type mismatch: {:?} != {:?}", left_type, right_type));
-            Type::Any
-        } else {
-            left_type
+    // Optionals have to be checked against null before anything can be done with them
+    fn require_value(&mut self, typename: Type) -> Type {
+        match typename {
+            Type::Optional(inner) => {
+                self.add_error(&format!("Optional value of type {:?} must be checked against null before use", Type::Optional(inner.clone())));
+                *inner
+            }
+            Type::Null => {
+                self.add_error("'null' can only be assigned to or compared with optional values");
+                Type::Unknown
+            }
+            other => other,
+        }
+    }
+
+    fn check_unary_expr(&mut self, op: Operator, operand: Expr, is_postfix: bool) -> (Expr, Type) {
+        let (operand, operand_type) = self.analyze_expr(operand);
+        let operand_type            = self.require_value(operand_type);
+
+        let result_type = match unary_type(&op, &operand_type) {
+            Ok(result_type) => result_type,
+            Err(message)    => {
+                self.add_error(&message);
+                result_type(&op)
+            }
+        };
+
+        (Expr::UnaryOp(op, operand.wrap(), is_postfix), result_type)
+    }
+
+    fn check_binary_expr(&mut self, op: Operator, left: Expr, right: Expr) -> (Expr, Type) {
+        match (op, left, right) {
+            (op @ (Operator::Eq | Operator::Neq), Expr::Null(_), value) | (op @ (Operator::Eq | Operator::Neq), value, Expr::Null(_)) => {
+                self.check_null_comparison(op, value)
+            }
+            (op, left, right) => self.check_operands(op, left, right),
+        }
+    }
+
+    fn check_operands(&mut self, op: Operator, left: Expr, right: Expr) -> (Expr, Type) {
+        let (left, left_type) = self.analyze_expr(left);
+
+        // The right side of `&&`/`||` sees the checks of the left side; it is lowered to code that only
+        // runs once the left side has decided when it reads a variable they are about
+        let (right, right_type) = match op {
+            Operator::And | Operator::Or => {
+                self.enter_scope();
+                self.narrow_by(&left, op == Operator::And);
+                let right = self.analyze_expr(right);
+                self.exit_scope();
+                right
+            }
+            _ => self.analyze_expr(right),
+        };
+
+        let left_type   = self.require_value(left_type);
+        let right_type  = self.require_value(right_type);
+
+        if matches!(op, Operator::Eq | Operator::Neq | Operator::Lt | Operator::Gt | Operator::Lte | Operator::Gte) && (is_nan(&left) || is_nan(&right)) {
+            self.lint(Lint::NanComparison, &format!("Comparison with NaN using '{}' is always {}; NaN is not equal to anything, itself included", op, op == Operator::Neq));
+        }
+
+        if matches!(op, Operator::Div | Operator::Mod) && constant_number(&right) == Some(0.0) {
+            self.lint(Lint::DivisionByZero, &format!("Division by zero using '{}'; SQF reports an error and gives 0", op));
         }
+
+        let result_type = match binary_type(&op, &left_type, &right_type) {
+            Ok(result_type) => result_type,
+            Err(message)    => {
+                self.add_error(&message);
+                result_type(&op)
+            }
+        };
+
+        (Expr::BinaryOp(op, left.wrap(), right.wrap()), result_type)
     }
 
-    fn check_array_init(&mut self, elements: Vec<Expr>) -> Type {
-        let element_type = elements.first().map(|el| self.analyze_expr(el.clone()));
-        if elements.iter().all(|el| element_type == Some(self.analyze_expr(el.clone()))) {
-            Type::Array(element_type.unwrap_or(Type::Any).wrap())
+    // `x == null` is normalized so that the null is always on the right
+    fn check_null_comparison(&mut self, op: Operator, value: Expr) -> (Expr, Type) {
+        let (value, value_type) = self.analyze_expr(value);
+
+        let inner = match value_type {
+            Type::Optional(inner)   => *inner,
+            Type::Any               => Type::Any,
+            Type::Unknown           => Type::Unknown,
+            other                   => {
+                self.add_error(&format!("Only optional values can be compared with null, got {:?}", other));
+                other
+            }
+        };
+
+        (Expr::BinaryOp(op, value.wrap(), Expr::Null(Some(inner)).wrap()), Type::Boolean)
+    }
+
+    // Casts that change the representation become calls to the conversion builtin; the rest only retype
+    fn check_cast(&mut self, value: Expr, typename: Type) -> (Expr, Type) {
+        let (value, value_type) = self.analyze_expr(value);
+        let value_type          = self.require_value(value_type);
+        let typename            = resolve_type(typename, &self.type_params);
+        self.check_type(&typename);
+
+        if self.is_assignable(&value_type, &typename) {
+            return (value, typename);
+        }
+
+        match prelude::conversion(&value_type, &typename) {
+            Some(builtin)   => (Expr::FuncCall(builtin.to_string(), vec![value]), typename),
+            None            => {
+                self.add_error(&format!("Cannot cast {:?} to {:?}", value_type, typename));
+                (value, typename)
+            }
+        }
+    }
+
+    // `is` compares SQF types, so it can only tell apart the types SQF represents differently
+    fn check_is(&mut self, value: Expr, typename: Type) -> (Expr, Type) {
+        let (value, value_type) = self.analyze_expr(value);
+        let typename            = resolve_type(typename, &self.type_params);
+        self.check_type(&typename);
+
+        let (members, optional) = match &value_type {
+            Type::Optional(inner)   => (union_members(inner), true),
+            other                   => (union_members(other), false),
+        };
+
+        match runtime_type(&typename) {
+            None => self.add_error(&format!("Type {:?} cannot be tested with 'is'", typename)),
+            Some(_) if matches!(value_type, Type::Any | Type::Unknown) => (),
+            Some(kind) => {
+                let alike: Vec<&Type> = members.iter().filter(|member| runtime_type(member) == Some(kind)).collect();
+
+                if let Some(other) = alike.iter().find(|member| ***member != typename) {
+                    self.add_error(&format!("'is {:?}' cannot tell {:?} and {:?} apart at run time", typename, typename, other));
+                } else if alike.is_empty() {
+                    self.lint(Lint::ConstantCondition, &format!("Type test is always false, a {:?} is never a {:?}", value_type, typename));
+                } else if members.len() == 1 && !optional {
+                    self.lint(Lint::ConstantCondition, &format!("Type test is always true, the value is already a {:?}", typename));
+                }
+            }
+        }
+
+        (Expr::Is(value.wrap(), typename), Type::Boolean)
+    }
+
+    fn check_array_init(&mut self, elements: Vec<Expr>) -> (Expr, Type) {
+        let (elements, types): (Vec<Expr>, Vec<Type>) = elements.into_iter().map(|el| self.analyze_expr(el)).unzip();
+        let element_type = types.first().cloned();
+
+        if types.iter().all(|t| Some(t) == element_type.as_ref()) {
+            (Expr::Array(elements), Type::Array(element_type.unwrap_or(Type::Any).wrap()))
         } else {
             self.add_error("Array elements have inconsistent types.");
-            Type::Any
+            (Expr::Array(elements), Type::Unknown)
         }
     }
 
-    fn check_array_access(&mut self, array_name: String, index: Expr) -> Type {
-        match self.scope.find_symbol(&array_name) {
-            Some(Symbol::Array { typename, size, .. }) => {
-                if self.analyze_expr(index.clone()) != Type::Number {
+    fn check_array_access(&mut self, array_name: String, index: Expr) -> (Expr, Type) {
+        self.check_assigned(&array_name);
+        self.scope.mark_used(&array_name);
+        let symbol              = self.scope.find_symbol(&array_name);
+        let (index, index_type) = self.analyze_expr(index);
+
+        let element_type = match symbol.map(|symbol| (symbol.typename().clone(), symbol)) {
+            Some((Type::Optional(_), _)) => {
+                self.add_error(&format!("Optional array '{}' must be checked against null before use", array_name));
+                Type::Unknown
+            }
+            Some((Type::Any | Type::Unknown, _)) => Type::Any,
+            Some((Type::Array(element), symbol)) => {
+                if index_type != Type::Number {
                     self.add_error("Array index must be a number.");
-                } else if let Expr::Number(i) = index {
-                    if (i as usize) >= size {
+                } else if let (Symbol::Array { size, .. }, Expr::Number(i)) = (&symbol, &index) {
+                    if (*i as usize) >= *size {
                         self.add_error(&format!("Array index out of bounds: {} >= {}", i, size));
                     }
                 }
-                if let Type::Array(t) = typename {
-                    *t
-                } else {
-                    self.add_error("Array type is not an array.");
-                    Type::Any
-                }
+                *element
             }
             _ => {
                 self.add_error(&format!("'{}' is not an array", array_name));
-                Type::Any
+                Type::Unknown
+            }
+        };
+
+        (Expr::ArrayAccess(self.scope.resolve(&array_name), index.wrap()), element_type)
+    }
+
+    /// Checks call arguments against a signature and returns them along with the call's type.
+    ///
+    /// Type parameters are inferred from the arguments and substituted into the return type.
+    /// Named arguments are put in parameter order and missing ones are filled in from the defaults.
+    fn check_call(&mut self, func_name: &str, type_params: &[String], params: &[Type], defaults: &[(String, Option<Expr>)], return_type: &Type, args: Vec<Expr>) -> (Vec<Expr>, Type) {
+        let args = self.analyze_args(args);
+
+        match self.bind_args(func_name, params, defaults, args.clone()) {
+            Ok((params, args))  => self.check_args(func_name, type_params, &params, return_type, args),
+            Err(error)          => {
+                self.add_error(&error);
+                (args.into_iter().map(|(_, arg, _)| arg).collect(), substitute(return_type, &HashMap::new(), &self.rigid_params(type_params)))
             }
         }
     }
 
-    fn check_func_call(&mut self, func_name: &str, args: Vec<Expr>) -> Type {
-        if let Some(Symbol::Func { return_type, params, .. }) = self.scope.find_symbol(func_name) {
-            if params.len() != args.len() {
-                self.add_error(&format!("Function '{}' expects {} arguments, got {}", func_name, params.len(), args.len()));
-            } else {
-                for (param_type, arg) in params.iter().zip(args) {
-                    let arg_type = self.analyze_expr(arg);
-                    if arg_type != *param_type {
-                        self.add_error(&format!("Argument type mismatch in '{}': expected {:?}, got {:?}", func_name, param_type, arg_type));
-                    }
+    fn analyze_args(&mut self, args: Vec<Expr>) -> Vec<(Option<String>, Expr, Type)> {
+        args.into_iter().map(|arg| match arg {
+            Expr::NamedArg(name, value) => {
+                let (value, value_type) = self.analyze_expr(*value);
+                (Some(name), value, value_type)
+            }
+            arg => {
+                let (arg, arg_type) = self.analyze_expr(arg);
+                (None, arg, arg_type)
+            }
+        }).collect()
+    }
+
+    // Matches analyzed arguments to parameters, one per parameter in order, and pairs them with the
+    // type each one is expected to have; a variadic parameter takes all the remaining ones
+    fn bind_args(&mut self, func_name: &str, params: &[Type], defaults: &[(String, Option<Expr>)], args: Vec<(Option<String>, Expr, Type)>) -> Result<BoundArgs, String> {
+        let (fixed, rest) = match params.split_last() {
+            Some((Type::Variadic(element), fixed))  => (fixed, Some(*element.clone())),
+            _                                       => (params, None),
+        };
+
+        let required    = fixed.len() - defaults.iter().filter(|(_, default)| default.is_some()).count();
+        let expected    = match rest {
+            Some(_)                             => format!("at least {}", required),
+            None if required == fixed.len()     => fixed.len().to_string(),
+            None                                => format!("{} to {}", required, fixed.len()),
+        };
+        let positional  = args.iter().filter(|(name, _, _)| name.is_none()).count();
+        let mut slots   = vec![None; fixed.len()];
+        let mut extra   = Vec::new();
+
+        if (rest.is_none() && positional > fixed.len()) || (positional < required && positional == args.len()) {
+            return Err(format!("Function '{}' expects {} arguments, got {}", func_name, expected, args.len()));
+        }
+
+        for (i, (name, arg, arg_type)) in args.into_iter().enumerate() {
+            let index = match name {
+                None if i >= positional     => return Err(format!("Positional argument after a named one in call to '{}'", func_name)),
+                None if i >= fixed.len()    => {
+                    extra.push(self.spread(arg, arg_type));
+                    continue;
                 }
+                None                        => i,
+                Some(_) if defaults.is_empty() => return Err(format!("Function '{}' does not take named arguments", func_name)),
+                Some(name)                  => defaults
+                                                .iter()
+                                                .position(|(param, _)| *param == name)
+                                                .ok_or(format!("Function '{}' has no parameter '{}'", func_name, name))?,
+            };
+
+            if index >= fixed.len() {
+                return Err(format!("Variadic parameter '{}' of '{}' can't be passed by name", defaults[index].0, func_name));
             }
-            return_type
-        } else {
-            self.add_error(&format!("Function '{}' is not defined", func_name));
-            Type::Any
+
+            if slots[index].is_some() {
+                return Err(format!("Parameter '{}' of '{}' is given more than once", defaults[index].0, func_name));
+            }
+            slots[index] = Some((arg, arg_type));
+        }
+
+        let mut bound = slots.into_iter().enumerate().map(|(i, slot)| match (slot, defaults.get(i)) {
+            (Some(arg), _)                          => Ok(arg),
+            (None, Some((_, Some(default))))        => Ok(self.analyze_expr(default.clone())),
+            (None, param)                           => Err(format!("Missing argument for parameter '{}' of '{}'", param.map_or("", |(name, _)| name), func_name)),
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let mut expected_types = fixed.to_vec();
+        expected_types.extend(rest.into_iter().flat_map(|element| std::iter::repeat_n(element, extra.len())));
+        bound.extend(extra);
+
+        Ok((expected_types, bound))
+    }
+
+    // An extra argument for a variadic parameter; the caller's own rest parameter stands for the arguments it collected
+    fn spread(&self, arg: Expr, arg_type: Type) -> (Expr, Type) {
+        match (arg, arg_type) {
+            (Expr::Identifier(name), Type::Array(element)) if self.rest_param.as_ref() == Some(&name) => (Expr::Spread(Expr::Identifier(name).wrap()), *element),
+            (arg, arg_type) => (arg, arg_type),
+        }
+    }
+
+    // Like `check_call`, for arguments that have already been bound to the parameters
+    fn check_args(&mut self, func_name: &str, type_params: &[String], params: &[Type], return_type: &Type, args: Vec<(Expr, Type)>) -> (Vec<Expr>, Type) {
+        let mut bindings = HashMap::new();
+        let mut checked = Vec::new();
+        let rigid       = self.rigid_params(type_params);
+
+        for (param_type, (arg, arg_type)) in params.iter().zip(args) {
+            if !self.unify(param_type, &arg_type, &mut bindings) {
+                let param_type = substitute(param_type, &bindings, &rigid);
+                self.report_mismatch(&arg_type, &param_type, format!("Argument type mismatch in '{}': expected {:?}, got {:?}", func_name, param_type, arg_type));
+            }
+            checked.push(coerce_null(arg, &substitute(param_type, &bindings, &rigid)));
+        }
+
+        for type_param in type_params {
+            if !bindings.contains_key(type_param) {
+                self.add_error(&format!("Cannot infer type parameter '{}' of '{}'", type_param, func_name));
+            }
+        }
+
+        (checked, substitute(return_type, &bindings, &rigid))
+    }
+
+    // Type parameters of the function being checked, which stand for themselves in the types of what it calls,
+    // unless the callee declares one of the same name
+    fn rigid_params(&self, callee_params: &[String]) -> Vec<String> {
+        self.type_params.iter().filter(|name| !callee_params.contains(name)).cloned().collect()
+    }
+
+    fn check_func_call(&mut self, func_name: String, args: Vec<Expr>) -> (Expr, Type) {
+        self.scope.mark_used(&func_name);
+
+        let (args, return_type) = match self.scope.find_symbol(&func_name) {
+            Some(Symbol::Func { .. }) if self.scope.find_overloads(&func_name).len() > 1 => {
+                return self.check_overloaded_call(func_name, args);
+            }
+            Some(Symbol::Func { return_type, type_params, params, defaults, .. }) => {
+                self.check_call(&func_name, &type_params, &params, &defaults, &return_type, args)
+            }
+            Some(Symbol::Var { typename: Type::Func(params, return_type), .. }) => {
+                self.check_call(&func_name, &[], &params, &[], &return_type, args)
+            }
+            Some(_) => {
+                self.add_error(&format!("'{}' is not a function", func_name));
+                (args, Type::Unknown)
+            }
+            None => {
+                self.add_error(&format!("Function '{}' is not defined", func_name));
+                (args, Type::Unknown)
+            }
+        };
+
+        (Expr::FuncCall(self.scope.resolve(&func_name), args), return_type)
+    }
+
+    /// Picks the overload whose parameters fit the argument types, preferring an exact match.
+    fn check_overloaded_call(&mut self, func_name: String, args: Vec<Expr>) -> (Expr, Type) {
+        let args                    = self.analyze_args(args);
+        let arg_types: Vec<Type>    = args.iter().map(|(_, _, t)| t.clone()).collect();
+        let mut candidates          = Vec::new();
+
+        for overload in self.scope.find_overloads(&func_name) {
+            let Symbol::Func { params, defaults, .. } = &overload else { continue };
+
+            if let Ok((expected, bound)) = self.bind_args(&func_name, params, defaults, args.clone()) {
+                if expected.iter().zip(&bound).all(|(param, (_, arg))| self.unify(param, arg, &mut HashMap::new())) {
+                    candidates.push((overload, expected, bound));
+                }
+            }
+        }
+
+        let exact: Vec<_> = candidates
+            .iter()
+            .filter(|(_, expected, bound)| expected.iter().zip(bound).all(|(param, (_, arg))| param == arg))
+            .collect();
+
+        let (chosen, expected, bound) = match (exact.as_slice(), candidates.as_slice()) {
+            ([overload], _)                     => (*overload).clone(),
+            ([], [overload])                    => overload.clone(),
+            ([], [])                            => {
+                let overloads: Vec<String> = self.scope.find_overloads(&func_name).iter().filter_map(|overload| match overload {
+                    Symbol::Func { params, return_type, .. } => Some(signature(params, return_type)),
+                    _ => None,
+                }).collect();
+                self.add_error(&format!("No overload of '{}' takes ({}); candidates are {}", func_name, type_list(&arg_types), overloads.join(", ")));
+                return (Expr::FuncCall(func_name, args.into_iter().map(|(_, arg, _)| arg).collect()), Type::Unknown);
+            }
+            _                                   => {
+                self.add_error(&format!("Ambiguous call to '{}' with argument types ({})", func_name, type_list(&arg_types)));
+                return (Expr::FuncCall(func_name, args.into_iter().map(|(_, arg, _)| arg).collect()), Type::Unknown);
+            }
+        };
+
+        let Symbol::Func { return_type, type_params, params, .. } = chosen else { unreachable!() };
+        let (args, return_type) = self.check_args(&func_name, &type_params, &expected, &return_type, bound);
+
+        (Expr::FuncCall(self.scope.overload_name(&func_name, &params), args), return_type)
+    }
+
+    fn check_new(&mut self, class: String, args: Vec<Expr>) -> (Expr, Type) {
+        if self.find_class(&class).is_none() {
+            self.add_error(&format!("Unknown class '{}'", class));
+            return (Expr::New(class, args), Type::Unknown);
         }
+
+        // A class without its own constructor inherits the nearest one up the chain
+        let (params, defaults) = self.class_chain(&class).into_iter().find_map(|symbol| match symbol {
+            Symbol::Class { ctor, .. } => ctor,
+            _ => None,
+        }).unwrap_or_default();
+
+        let (args, class_type) = self.check_call(&class, &[], &params, &defaults, &Type::Class(class.clone()), args);
+        (Expr::New(class, args), class_type)
+    }
+
+    fn class_of(&mut self, target: Expr, member: &str) -> (Expr, Option<String>) {
+        let (target, target_type) = self.analyze_expr(target);
+
+        let class = match self.require_value(target_type) {
+            Type::Class(class)  => Some(class),
+            Type::Any | Type::Unknown => None,
+            other               => {
+                self.add_error(&format!("Cannot access member '{}' of non-class type {:?}", member, other));
+                None
+            }
+        };
+
+        (target, class)
+    }
+
+    fn check_member(&mut self, target: Expr, field: String) -> (Expr, Type) {
+        let (target, class) = self.class_of(target, &field);
+
+        let field_type = match class.map(|class| (self.find_member(&class, &field), class)) {
+            Some((Some(Symbol::Var { typename, .. }), _)) => typename,
+            Some((Some(_), class)) => {
+                self.add_error(&format!("'{}.{}' is a method, not a field", class, field));
+                Type::Unknown
+            }
+            Some((None, class)) => {
+                self.add_error(&format!("Class '{}' has no member '{}'", class, field));
+                Type::Unknown
+            }
+            None => Type::Any,
+        };
+
+        (Expr::Member(target.wrap(), field), field_type)
+    }
+
+    fn check_method_call(&mut self, target: Expr, method: String, args: Vec<Expr>) -> (Expr, Type) {
+        let (target, class) = self.class_of(target, &method);
+
+        let (args, return_type) = match class.map(|class| (self.find_member(&class, &method), class)) {
+            Some((Some(Symbol::Func { return_type, type_params, params, defaults, .. }), class)) => {
+                self.check_call(&format!("{}.{}", class, method), &type_params, &params, &defaults, &return_type, args)
+            }
+            Some((Some(_), class)) => {
+                self.add_error(&format!("'{}.{}' is a field, not a method", class, method));
+                (args, Type::Unknown)
+            }
+            Some((None, class)) => {
+                self.add_error(&format!("Class '{}' has no member '{}'", class, method));
+                (args, Type::Unknown)
+            }
+            None => (args, Type::Any),
+        };
+
+        (Expr::MethodCall(target.wrap(), method, args), return_type)
+    }
+
+    fn check_member_assignment(&mut self, target: Expr, field: String, value: Expr) -> Stmt {
+        if value == Expr::Member(target.clone().wrap(), field.clone()) {
+            self.lint(Lint::SelfAssignment, &format!("Field '{}' is assigned to itself", field));
+        }
+
+        let (member, field_type)    = self.check_member(target, field);
+        let (value, value_type)     = self.analyze_expr(value);
+        let Expr::Member(target, field) = member else { unreachable!() };
+
+        self.expect_assignable(&value_type, &field_type, format!("Type mismatch in assignment to field '{}'", field));
+        Stmt::MemberAssign(*target, field, coerce_null(value, &field_type))
     }
 
-    fn check_assignment(&mut self, name: String, value: Expr, stmt: Stmt) -> Stmt {
-        if let Some(symbol) = self.scope.find_symbol(&name) {
-            if symbol.typename() != &self.analyze_expr(value) {
-                self.add_error(&format!("Type mismatch in assignment to '{}'", name));
+    fn check_assignment(&mut self, name: String, value: Expr) -> Stmt {
+        if value == Expr::Identifier(name.clone()) {
+            self.lint(Lint::SelfAssignment, &format!("'{}' is assigned to itself", name));
+        }
+
+        let (value, value_type) = self.analyze_expr(value);
+
+        if prelude::find(&name).is_some() {
+            self.add_error(&format!("Cannot assign to builtin '{}'", name));
+        } else if let Some(symbol) = self.scope.find_symbol(&name) {
+            let declared = symbol.declared_type().clone();
+            self.expect_assignable(&value_type, &declared, format!("Type mismatch in assignment to '{}'", name));
+
+            // A fresh value replaces whatever was known about the old one
+            if matches!(declared, Type::Optional(_) | Type::Union(_)) {
+                self.scope.forget_narrowing(&name);
+                if let Some(narrowed) = narrowed_by_value(&declared, &value_type) {
+                    self.scope.define(Symbol::Narrowed { typename: narrowed, name: name.clone(), declared: declared.clone() });
+                }
             }
+
+            self.unassigned.retain(|unassigned| *unassigned != name);
+            return Stmt::Assign(self.scope.resolve(&name), coerce_null(value, &declared));
         } else {
             self.add_error(&format!("Undefined variable: '{}'", name));
         }
-        stmt
+
+        Stmt::Assign(name, value)
     }
 
     fn check_var_decl(&mut self, typename: Type, name: String, value: Option<Expr>, stmt: Stmt) -> Stmt {
-        if self.scope.find_symbol(&name).is_some() {
+        let declared = resolve_type(typename, &self.type_params);
+        self.check_type(&declared);
+
+        if self.scope.find_local(&name).is_some() {
             self.add_error(&format!("Redeclaration of '{}'", name));
-        } else if let Some(expr) = value {
-            let value_type = self.analyze_expr(expr.clone());
-            if value_type != typename {
-                self.add_error(&format!("Type mismatch in declaration of '{}'", name));
-            }
+            return stmt;
+        }
+
+        if prelude::find(&name).is_some() {
+            self.add_error(&format!("Variable '{}' cannot shadow a builtin", name));
+            return stmt;
+        }
+
+        if let Some(Symbol::Var { .. } | Symbol::Array { .. } | Symbol::Narrowed { .. }) = self.scope.find_symbol(&name) {
+            self.lint(Lint::ShadowedVariable, &format!("Variable '{}' shadows an outer variable", name));
+        }
 
-            if let Expr::Array(v) = expr {
-                let size = v.len();
-                self.scope.define(Symbol::Array { typename, name, size });
-            } else {
-                self.scope.define(Symbol::Var { typename, name });
+        // Bare blocks don't get a scope of their own in SQF, so a `private` there would overwrite the outer variable
+        let emitted = if self.scope.in_function(&name) {
+            self.renamed += 1;
+            format!("_{}_{}", name, self.renamed)
+        } else {
+            name.clone()
+        };
+
+        let (value, value_type) = match value.map(|expr| self.analyze_expr(expr)) {
+            Some((value, value_type))   => (Some(value), Some(value_type)),
+            None                        => (None, None),
+        };
+
+        let typename = if declared == Type::Auto {
+            self.infer_type(&name, value_type.as_ref())
+        } else {
+            if let Some(value_type) = &value_type {
+                self.expect_assignable(value_type, &declared, format!("Type mismatch in declaration of '{}'", name));
             }
+            declared
+        };
+
+        self.declare(Lint::UnusedVariable, &name);
+
+        if let Some(Expr::Array(v)) = &value {
+            self.scope.define(Symbol::Array { typename: typename.clone(), name: name.clone(), size: v.len() });
         } else {
-            self.scope.define(Symbol::Var { typename, name });
+            self.scope.define(Symbol::Var { typename: typename.clone(), name: name.clone() });
         }
-        stmt
+
+        // Optionals start out null, so only the other types need a value before they are read
+        if value.is_none() && !matches!(typename, Type::Optional(_)) {
+            self.unassigned.push(name.clone());
+        }
+
+        if let Some(narrowed) = value_type.and_then(|value_type| narrowed_by_value(&typename, &value_type)) {
+            self.scope.define(Symbol::Narrowed { typename: narrowed, name: name.clone(), declared: typename.clone() });
+        }
+
+        if emitted != name {
+            self.scope.renames.push((name.clone(), emitted.clone()));
+        }
+
+        let value = value.map(|value| coerce_null(value, &typename));
+        Stmt::VarDecl(typename, emitted, value)
+    }
+
+    // Type of an `auto`/`let` declaration, taken from its initializer
+    fn infer_type(&mut self, name: &str, value_type: Option<&Type>) -> Type {
+        match value_type {
+            None => {
+                self.add_error(&format!("Cannot infer the type of '{}' without an initializer; an explicit type is required", name));
+                Type::Unknown
+            }
+            // Whatever went wrong with the initializer has been reported already
+            Some(Type::Unknown) => Type::Unknown,
+            Some(value_type) if !is_inferable(value_type) => {
+                self.add_error(&format!("Cannot infer the type of '{}' from {:?}; an explicit type is required", name, value_type));
+                Type::Unknown
+            }
+            Some(value_type) => value_type.clone(),
+        }
+    }
+
+    /// Registers the top-level functions up front, so they can be called before their definition
+    /// and call each other without prototypes.
+    fn collect_declarations(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            if let Stmt::FuncDef(return_type, name, type_params, params, _) = stmt.unattributed() {
+                let (return_type, params) = resolve_signature(type_params, return_type.clone(), params.clone());
+                let defaults = param_defaults(&params);
+                let params: Vec<Type> = params.into_iter().map(|(t, _, _)| t).collect();
+
+                // Clashes are reported when the definition itself is checked
+                let overloads = self.scope.local_overloads(name);
+                if self.scope.find_symbol(name).is_some() && (overloads.is_empty() || overloads.iter().any(|(p, _)| *p == params)) {
+                    continue;
+                }
+
+                self.scope.define(Symbol::Func { return_type, name: name.clone(), type_params: type_params.clone(), params: params.clone(), defaults });
+                self.undefined.push((name.clone(), params));
+            }
+        }
+    }
+
+    fn is_pending(&self, name: &str, params: &[Type]) -> bool {
+        self.undefined.iter().any(|(n, p)| n == name && p == params)
     }
 
     fn check_func_decl(&mut self, return_type: Type, name: String, params: Vec<Type>, stmt: Stmt) -> Stmt {
-        if self.scope.find_symbol(&name).is_some() {
+        let overloads = if prelude::find(&name).is_none() { self.scope.local_overloads(&name) } else { Vec::new() };
+
+        match overloads.iter().find(|(p, _)| *p == params) {
+            Some((_, defined)) if *defined != return_type => self.add_error(&format!(
+                "Prototype of '{}' does not match its definition: declared as {}, defined as {}",
+                name, signature(&params, &return_type), signature(&params, defined)
+            )),
+            Some(_) => (),
+            None => match overloads.as_slice() {
+                [(defined_params, defined_return)] => self.add_error(&format!(
+                    "Prototype of '{}' does not match its definition: declared as {}, defined as {}",
+                    name, signature(&params, &return_type), signature(defined_params, defined_return)
+                )),
+                [_, ..] => self.add_error(&format!("Prototype of '{}' matches none of its overloads: declared as {}", name, signature(&params, &return_type))),
+                [] if self.scope.find_symbol(&name).is_some() => self.add_error(&format!("Redeclaration of '{}'", name)),
+                [] => {
+                    self.scope.define(Symbol::Func { return_type, name: name.clone(), type_params: Vec::new(), params: params.clone(), defaults: Vec::new() });
+                    self.undefined.push((name, params));
+                }
+            },
+        }
+        stmt
+    }
+
+    fn check_func_def(&mut self, return_type: Type, name: String, type_params: Vec<String>, params: Vec<(Type, String, Option<Expr>)>, body: Stmt, stmt: Stmt) -> Stmt {
+        let (resolved_return, resolved_params) = resolve_signature(&type_params, return_type.clone(), params.clone());
+        let param_types: Vec<Type> = resolved_params.iter().map(|(t, _, _)| t.clone()).collect();
+        let overloads = if prelude::find(&name).is_none() { self.scope.local_overloads(&name) } else { Vec::new() };
+
+        // The declaration this definition completes: the overload with the same parameters, or a lone prototype that got them wrong
+        let declared = match overloads.iter().find(|(p, _)| *p == param_types) {
+            Some((p, _)) if !self.is_pending(&name, p) => {
+                self.add_error(&format!("Redeclaration of '{}'", name));
+                return stmt;
+            }
+            Some(declared) => Some(declared.clone()),
+            None => match overloads.as_slice() {
+                [(p, r)] if self.is_pending(&name, p) => Some((p.clone(), r.clone())),
+                _ => None,
+            },
+        };
+
+        if overloads.is_empty() && self.scope.find_symbol(&name).is_some() {
             self.add_error(&format!("Redeclaration of '{}'", name));
+            return stmt;
+        }
+
+        self.check_type_params(&type_params);
+        let params = self.check_defaults(&name, params, &resolved_params);
+        let symbol = Symbol::Func { return_type: resolved_return.clone(), name: name.clone(), type_params: type_params.clone(), params: param_types.clone(), defaults: param_defaults(&params) };
+
+        if let Some((declared_params, declared_return)) = declared {
+            if (&declared_params, &declared_return) != (&param_types, &resolved_return) {
+                self.add_error(&format!(
+                    "Prototype of '{}' does not match its definition: declared as {}, defined as {}",
+                    name, signature(&declared_params, &declared_return), signature(&param_types, &resolved_return)
+                ));
+            }
+
+            self.undefined.retain(|(n, p)| *n != name || *p != declared_params);
+            self.scope.replace_overload(&declared_params, symbol);
         } else {
-            self.scope.define(Symbol::Func { return_type, name, params });
+            self.scope.define(symbol);
         }
-        stmt
+
+        self.declare(Lint::UnusedFunction, &name);
+
+        let emitted = self.scope.overload_name(&name, &param_types);
+        let body    = self.check_function_body(&resolved_return, type_params.clone(), resolved_params, body, None);
+        Stmt::FuncDef(return_type, emitted, type_params, params, body.wrap())
     }
 
-    fn check_func_def(&mut self, return_type: Type, name: String, params: Vec<(Type, String)>, body: Box<Stmt>, stmt: Stmt) -> Stmt {
+    // Defaults have to be constants of the parameter's type, and only trailing parameters can have one.
+    // A variadic parameter has to come last and can't have a default, it is empty when not given.
+    fn check_defaults(&mut self, func_name: &str, params: Vec<(Type, String, Option<Expr>)>, resolved: &[(Type, String, Option<Expr>)]) -> Vec<(Type, String, Option<Expr>)> {
+        let mut defaulted   = false;
+        let last            = params.len().saturating_sub(1);
+
+        params.into_iter().zip(resolved).enumerate().map(|(i, ((typename, name, default), (resolved_type, _, _)))| {
+            if let Type::Variadic(_) = typename {
+                if i != last {
+                    self.add_error(&format!("Only the last parameter of '{}' can be variadic", func_name));
+                }
+                if default.is_some() {
+                    self.add_error(&format!("Variadic parameter '{}' of '{}' cannot have a default value", name, func_name));
+                }
+                return (typename, name, None);
+            }
+
+            let Some(default) = default else {
+                if defaulted {
+                    self.add_error(&format!("Parameter '{}' of '{}' needs a default value, as the parameters before it have one", name, func_name));
+                }
+                return (typename, name, None);
+            };
+
+            defaulted = true;
+            let (default, default_type) = self.analyze_expr(default);
+
+            if !is_constant(&default) {
+                self.add_error(&format!("Default value of parameter '{}' of '{}' must be a constant", name, func_name));
+            }
+            self.expect_assignable(&default_type, resolved_type, format!("Type mismatch in default value of parameter '{}' of '{}'", name, func_name));
+
+            let default = coerce_null(default, resolved_type);
+            (typename, name, Some(default))
+        }).collect()
+    }
+
+    fn check_type_params(&mut self, type_params: &[String]) {
+        for (i, type_param) in type_params.iter().enumerate() {
+            if type_params[..i].contains(type_param) {
+                self.add_error(&format!("Duplicate type parameter '{}'", type_param));
+            }
+        }
+    }
+
+    fn check_function_body(&mut self, return_type: &Type, type_params: Vec<String>, params: Vec<(Type, String, Option<Expr>)>, body: Stmt, class: Option<&str>) -> Stmt {
+        let outer_type_params   = std::mem::replace(&mut self.type_params, type_params);
+        let outer_return_type   = std::mem::replace(&mut self.return_type, return_type.clone());
+        let rest_param          = params.last().filter(|(typename, _, _)| matches!(typename, Type::Variadic(_))).map(|(_, name, _)| name.clone());
+        let outer_rest_param    = std::mem::replace(&mut self.rest_param, rest_param);
+        // Functions run whenever they are called, by which point the outer variables may be set
+        let outer_unassigned    = std::mem::take(&mut self.unassigned);
+
+        self.check_type(return_type);
+        self.enter_scope();
+        self.scope.function = true;
+        if let Some(class) = class {
+            self.scope.define(Symbol::Var { typename: Type::Class(class.to_string()), name: "self".to_string() });
+        }
+        for (param_type, param_name, _) in params {
+            self.check_type(&param_type);
+
+            match self.scope.find_symbol(&param_name) {
+                _ if self.scope.find_local(&param_name).is_some() => self.add_error(&format!("Duplicate parameter '{}'", param_name)),
+                Some(_) if prelude::find(&param_name).is_some() => self.add_error(&format!("Parameter '{}' cannot shadow a builtin", param_name)),
+                Some(Symbol::Var { .. } | Symbol::Array { .. } | Symbol::Narrowed { .. }) => {
+                    self.lint(Lint::ShadowedVariable, &format!("Parameter '{}' shadows an outer variable", param_name));
+                }
+                _ => (),
+            }
+
+            // The extra arguments are collected into an array
+            let param_type = match param_type {
+                Type::Variadic(element) => Type::Array(element),
+                param_type              => param_type,
+            };
+
+            self.declare(Lint::UnusedParameter, &param_name);
+            self.scope.define(Symbol::Var { typename: param_type, name: param_name });
+        }
+        let body = self.analyze(body);
+        self.exit_scope();
+
+        self.type_params = outer_type_params;
+        self.return_type = outer_return_type;
+        self.rest_param  = outer_rest_param;
+        self.unassigned  = outer_unassigned;
+        body
+    }
+
+    fn check_class_def(&mut self, name: String, base: Option<String>, members: Vec<Stmt>, stmt: Stmt) -> Stmt {
         if self.scope.find_symbol(&name).is_some() {
             self.add_error(&format!("Redeclaration of '{}'", name));
-        } else {
-            let param_types = params.iter().map(|(t, _)| t.clone()).collect();
-            self.scope.define(Symbol::Func { return_type: return_type.clone(), name: name.clone(), params: param_types });
-            self.enter_scope();
-            for (param_type, param_name) in params {
-                self.scope.define(Symbol::Var { typename: param_type, name: param_name });
+            return stmt;
+        }
+
+        if let Some(base) = &base {
+            if self.find_class(base).is_none() {
+                self.add_error(&format!("Unknown base class '{}' for '{}'", base, name));
             }
-            self.analyze(*body);
-            self.exit_scope();
         }
-        stmt
+
+        let mut fields  = Vec::new();
+        let mut methods = Vec::new();
+        let mut ctor    = None;
+
+        for member in &members {
+            match member.unattributed() {
+                Stmt::VarDecl(typename, field, _) => {
+                    if *typename == Type::Auto {
+                        self.add_error(&format!("Field '{}.{}' needs an explicit type", name, field));
+                    }
+
+                    if fields.iter().any(|(_, f)| f == field) {
+                        self.add_error(&format!("Duplicate field '{}' in class '{}'", field, name));
+                    } else if base.as_ref().and_then(|base| self.find_member(base, field)).is_some() {
+                        self.add_error(&format!("Field '{}.{}' is already declared in a base class", name, field));
+                    }
+                    fields.push((typename.clone(), field.clone()));
+                }
+                Stmt::FuncDef(return_type, method, type_params, params, _) => {
+                    self.check_type_params(type_params);
+                    let (return_type, params)   = resolve_signature(type_params, return_type.clone(), params.clone());
+                    let defaults                = param_defaults(&params);
+                    let param_types : Vec<Type> = params.into_iter().map(|(t, _, _)| t).collect();
+                    let return_type             = &return_type;
+
+                    if *method == name {
+                        if ctor.is_some() {
+                            self.add_error(&format!("Class '{}' has more than one constructor", name));
+                        }
+                        ctor = Some((param_types, defaults));
+                    } else if let Some(class) = method.strip_prefix('~') {
+                        if class != name {
+                            self.add_error(&format!("Destructor '{}' does not match class '{}'", method, name));
+                        }
+                    } else if *return_type == Type::Class(method.clone()) {
+                        self.add_error(&format!("Constructor '{}' does not match class '{}'", method, name));
+                    } else {
+                        if fields.iter().any(|(_, f)| f == method) || methods.iter().any(|m: &Symbol| m.name() == method) {
+                            self.add_error(&format!("Duplicate member '{}' in class '{}'", method, name));
+                        }
+
+                        match base.as_ref().and_then(|base| self.find_member(base, method)) {
+                            Some(Symbol::Func { return_type: base_return, params: base_params, .. })
+                                if base_return != *return_type || base_params != param_types => {
+                                self.add_error(&format!("Method '{}.{}' overrides a base method with an incompatible signature", name, method));
+                            }
+                            Some(Symbol::Var { .. }) => self.add_error(&format!("Method '{}.{}' conflicts with an inherited field", name, method)),
+                            _ => (),
+                        }
+
+                        methods.push(Symbol::Func { return_type: return_type.clone(), name: method.clone(), type_params: type_params.clone(), params: param_types, defaults });
+                    }
+                }
+                _ => self.add_error(&format!("Unexpected statement in class '{}'", name)),
+            }
+        }
+
+        // Defined up front so that methods can refer to their own class
+        self.scope.define(Symbol::Class { typename: Type::Class(name.clone()), name: name.clone(), base: base.clone(), fields, methods, ctor });
+
+        let members = members.into_iter().map(|member| self.check_class_member(&name, member)).collect();
+
+        Stmt::ClassDef(name, base, members)
+    }
+
+    fn check_class_member(&mut self, class: &str, member: Stmt) -> Stmt {
+        match member {
+            Stmt::VarDecl(typename, field, value) => {
+                self.check_type(&typename);
+                let value = value.map(|value| {
+                    self.enter_scope();
+                    self.scope.define(Symbol::Var { typename: Type::Class(class.to_string()), name: "self".to_string() });
+                    let (value, value_type) = self.analyze_expr(value);
+                    self.exit_scope();
+
+                    self.expect_assignable(&value_type, &typename, format!("Type mismatch in initializer of field '{}.{}'", class, field));
+                    coerce_null(value, &typename)
+                });
+                Stmt::VarDecl(typename, field, value)
+            }
+            Stmt::FuncDef(return_type, method, type_params, params, body) => {
+                let body_return                 = if method == class { Type::Void } else { return_type.clone() };
+                let (body_return, resolved)     = resolve_signature(&type_params, body_return, params.clone());
+                let params                      = self.check_defaults(&format!("{}.{}", class, method), params, &resolved);
+                let body                        = self.check_function_body(&body_return, type_params.clone(), resolved, *body, Some(class));
+                Stmt::FuncDef(return_type, method, type_params, params, body.wrap())
+            }
+            Stmt::Attributed(attributes, member) => {
+                for error in self.lints.enter(&attributes) {
+                    self.add_error(&error);
+                }
+                let member = self.check_class_member(class, *member);
+                self.lints.exit();
+
+                Stmt::Attributed(attributes, member.wrap())
+            }
+            other => other,
+        }
+    }
+
+    fn find_class(&self, name: &str) -> Option<Symbol> {
+        match self.scope.find_symbol(name) {
+            Some(class @ Symbol::Class { .. }) => Some(class),
+            _ => None,
+        }
+    }
+
+    // The class itself followed by its ancestors, most derived first
+    fn class_chain(&self, name: &str) -> Vec<Symbol> {
+        let mut chain   = Vec::new();
+        let mut next    = Some(name.to_string());
+
+        while let Some(class) = next.take().and_then(|name| self.find_class(&name)) {
+            if let Symbol::Class { base, .. } = &class {
+                next = base.clone().filter(|base| chain.iter().all(|c: &Symbol| c.name() != base));
+            }
+            chain.push(class);
+        }
+
+        chain
+    }
+
+    fn find_member(&self, class: &str, member: &str) -> Option<Symbol> {
+        self.class_chain(class).into_iter().find_map(|symbol| match symbol {
+            Symbol::Class { fields, methods, .. } => fields
+                .into_iter()
+                .find(|(_, field)| field == member)
+                .map(|(typename, name)| Symbol::Var { typename, name })
+                .or_else(|| methods.into_iter().find(|method| method.name() == member)),
+            _ => None,
+        })
+    }
+
+    fn is_subclass(&self, derived: &str, base: &str) -> bool {
+        self.class_chain(derived).iter().any(|class| class.name() == base)
+    }
+
+    fn is_assignable(&self, from: &Type, to: &Type) -> bool {
+        match (from, to) {
+            (Type::Any | Type::Unknown, _) | (_, Type::Any | Type::Unknown) => true,
+            (Type::Null, Type::Optional(_))             => true,
+            (Type::Optional(from), Type::Optional(to))  => self.is_assignable(from, to),
+            (Type::Null, _) | (Type::Optional(_), _)    => false,
+            (from, Type::Optional(to))                  => self.is_assignable(from, to),
+            (Type::Class(derived), Type::Class(base))   => self.is_subclass(derived, base),
+            (Type::Union(from), to)                     => from.iter().all(|from| self.is_assignable(from, to)),
+            (from, Type::Union(to))                     => to.iter().any(|to| self.is_assignable(from, to)),
+            (Type::Array(from), Type::Array(to))        => self.is_assignable(from, to),
+            (Type::Func(from_params, from_ret), Type::Func(to_params, to_ret)) => {
+                from_params.len() == to_params.len()
+                    && to_params.iter().zip(from_params).all(|(to, from)| self.is_assignable(to, from))
+                    && self.is_assignable(from_ret, to_ret)
+            }
+            _ => from == to,
+        }
+    }
+
+    // Points out unchecked optionals and misplaced nulls instead of just naming the types
+    fn report_mismatch(&mut self, from: &Type, to: &Type, mismatch: String) {
+        match from {
+            Type::Optional(inner) if self.is_assignable(inner, to) => {
+                self.add_error(&format!("{}: optional value of type {:?} must be checked against null first", mismatch, from));
+            }
+            Type::Null => self.add_error(&format!("{}: null can only be assigned to optional types, not {:?}", mismatch, to)),
+            _ => self.add_error(&mismatch),
+        }
+    }
+
+    fn expect_assignable(&mut self, from: &Type, to: &Type, mismatch: String) {
+        if !self.is_assignable(from, to) {
+            self.report_mismatch(from, to, mismatch);
+        }
+    }
+
+    /// Matches an argument type against a parameter type, binding the type parameters it mentions.
+    fn unify(&self, param: &Type, arg: &Type, bindings: &mut HashMap<String, Type>) -> bool {
+        match (param, arg) {
+            (_, Type::Any | Type::Unknown) => true,
+            (Type::Param(name), _) => match bindings.get(name) {
+                Some(bound) if self.is_assignable(arg, bound) => true,
+                Some(bound) if self.is_assignable(bound, arg) => { bindings.insert(name.clone(), arg.clone()); true }
+                Some(_) => false,
+                None    => { bindings.insert(name.clone(), arg.clone()); true }
+            },
+            (Type::Optional(_), Type::Null) => true,
+            (Type::Optional(param), Type::Optional(arg)) => self.unify(param, arg, bindings),
+            (Type::Optional(param), _) => self.unify(param, arg, bindings),
+            (Type::Array(param), Type::Array(arg)) => self.unify(param, arg, bindings),
+            (Type::Func(params, ret), Type::Func(args, arg_ret)) => {
+                params.len() == args.len()
+                    && params.iter().zip(args).all(|(param, arg)| self.unify(param, arg, bindings))
+                    && self.unify(ret, arg_ret, bindings)
+            }
+            _ => self.is_assignable(arg, param),
+        }
+    }
+
+    fn check_type(&mut self, typename: &Type) {
+        match typename {
+            Type::Array(element) | Type::Variadic(element) => self.check_type(element),
+            Type::Func(params, ret) => {
+                params.iter().for_each(|param| self.check_type(param));
+                self.check_type(ret);
+            }
+            Type::Optional(inner) if matches!(**inner, Type::Void | Type::Optional(_)) => {
+                self.add_error(&format!("Type {:?} cannot be made optional", inner));
+            }
+            Type::Optional(inner)   => self.check_type(inner),
+            Type::Union(members)    => {
+                for (i, member) in members.iter().enumerate() {
+                    if matches!(member, Type::Void | Type::Any | Type::Optional(_)) {
+                        self.add_error(&format!("Type {:?} cannot be part of a union", member));
+                    } else if members[..i].contains(member) {
+                        self.add_error(&format!("Type {:?} appears more than once in a union", member));
+                    }
+                    self.check_type(member);
+                }
+            }
+            Type::Class(class) if self.find_class(class).is_none() => {
+                self.add_error(&format!("Unknown type '{}'", class));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Operator typing table for unary operators.
+fn unary_type(op: &Operator, operand: &Type) -> Result<Type, String> {
+    match (op, operand) {
+        (_, Type::Any | Type::Unknown)                              => Ok(result_type(op)),
+        (Operator::Neg | Operator::Inc | Operator::Dec, Type::Number) => Ok(Type::Number),
+        (Operator::Not, Type::Boolean)                              => Ok(Type::Boolean),
+        (Operator::Not, _)                                          => Err(format!("Operator '!' expects a boolean, got {:?}", operand)),
+        _                                                           => Err(format!("Operator '{}' expects a number, got {:?}", op, operand)),
+    }
+}
+
+/// Operator typing table for binary operators.
+///
+/// `+` also concatenates strings and arrays, comparisons and logic yield booleans, and `==`/`!=`
+/// only accept the types SQF can compare with them.
+fn binary_type(op: &Operator, left: &Type, right: &Type) -> Result<Type, String> {
+    use Operator::*;
+
+    match (op, left, right) {
+        (Add, Type::String, Type::Number) | (Add, Type::Number, Type::String) => Err(format!(
+            "Operator '+' cannot mix {:?} and {:?}; strings and numbers don't mix implicitly, convert with `as string`, `as number`, str() or parseNumber()", left, right
+        )),
+        (_, Type::Any | Type::Unknown, other) | (_, other, Type::Any | Type::Unknown) => match op {
+            Eq | Neq | Lt | Gt | Lte | Gte | And | Or   => Ok(Type::Boolean),
+            _                                           => Ok(other.clone()),
+        },
+        (Add | Sub | Mul | Div | Mod, Type::Number, Type::Number)   => Ok(Type::Number),
+        (Add, Type::String, Type::String)                           => Ok(Type::String),
+        (Add, Type::Array(l), Type::Array(r)) if l == r             => Ok(left.clone()),
+        (Add, Type::Array(_), Type::Array(_))                       => Err(format!("Cannot concatenate {:?} and {:?}; array element types differ", left, right)),
+        (Add, _, _)                                                 => Err(format!("Operator '+' expects numbers, strings or arrays, got {:?} and {:?}", left, right)),
+        (Sub | Mul | Div | Mod, _, _)                               => Err(format!("Operator '{}' expects numbers, got {:?} and {:?}", op, left, right)),
+        (Lt | Gt | Lte | Gte, Type::Number, Type::Number)           => Ok(Type::Boolean),
+        (Lt | Gt | Lte | Gte, _, _)                                 => Err(format!("Operator '{}' compares numbers, got {:?} and {:?}", op, left, right)),
+        (And | Or, Type::Boolean, Type::Boolean)                    => Ok(Type::Boolean),
+        (And | Or, _, _)                                            => Err(format!("Operator '{}' expects booleans, got {:?} and {:?}", op, left, right)),
+        (Eq | Neq, _, _) if left != right                           => Err(format!("Operator '{}' compares values of different types: {:?} and {:?}", op, left, right)),
+        (Eq | Neq, Type::Number | Type::String | Type::Object | Type::Group | Type::Control, _) => Ok(Type::Boolean),
+        (Eq | Neq, _, _)                                            => Err(format!("Operator '{}' cannot compare values of type {:?}", op, left)),
+        _                                                           => Err(format!("Operator '{}' is not a binary operator", op)),
+    }
+}
+
+// Type an operator produces, used to keep going after a typing error
+fn result_type(op: &Operator) -> Type {
+    match op {
+        Operator::Not | Operator::And | Operator::Or | Operator::Eq | Operator::Neq
+            | Operator::Lt | Operator::Gt | Operator::Lte | Operator::Gte => Type::Boolean,
+        _ => Type::Unknown,
+    }
+}
+
+fn signature(params: &[Type], return_type: &Type) -> String {
+    format!("({}) -> {:?}", type_list(params), return_type)
+}
+
+fn type_list(types: &[Type]) -> String {
+    types.iter().map(|t| format!("{:?}", t)).collect::<Vec<_>>().join(", ")
+}
+
+fn is_nan(expr: &Expr) -> bool {
+    match expr {
+        Expr::Number(n) => n.is_nan(),
+        _               => false,
+    }
+}
+
+fn is_empty_block(stmt: &Stmt) -> bool {
+    *stmt == Stmt::Block(Vec::new())
+}
+
+// Types whose values are never null, so assigning one to an optional variable narrows it
+fn holds_value(typename: &Type) -> bool {
+    !matches!(typename, Type::Optional(_) | Type::Null | Type::Any | Type::Unknown)
+}
+
+// Gives a `null` literal the type it ends up in, so it can be lowered to the matching SQF null
+fn coerce_null(expr: Expr, expected: &Type) -> Expr {
+    match (expr, expected) {
+        (Expr::Null(None), Type::Optional(inner)) => Expr::Null(Some(*inner.clone())),
+        (expr, _) => expr,
+    }
+}
+
+// What a variable of the declared type is known to hold right after it is given a value of another
+fn narrowed_by_value(declared: &Type, value_type: &Type) -> Option<Type> {
+    match declared {
+        Type::Optional(inner) if holds_value(value_type)        => Some(*inner.clone()),
+        Type::Union(members) if members.contains(value_type)    => Some(value_type.clone()),
+        _                                                       => None,
+    }
+}
+
+// SQF's name for how values of the type are represented, if `isEqualType` can recognize them
+fn runtime_type(typename: &Type) -> Option<&'static str> {
+    match typename {
+        Type::Number    => Some("SCALAR"),
+        Type::String    => Some("STRING"),
+        Type::Boolean   => Some("BOOL"),
+        Type::Object    => Some("OBJECT"),
+        Type::Group     => Some("GROUP"),
+        Type::Control   => Some("CONTROL"),
+        Type::Array(_)  => Some("ARRAY"),
+        Type::Func(..)  => Some("CODE"),
+        Type::Class(_)  => Some("HASHMAP"),
+        _               => None,
+    }
+}
+
+fn union_members(typename: &Type) -> Vec<Type> {
+    match typename {
+        Type::Union(members)    => members.clone(),
+        other                   => vec![other.clone()],
+    }
+}
+
+// The union left once a failed `is` test rules out a type, unless nothing would be left
+fn union_without(members: &[Type], tested: &Type) -> Option<Type> {
+    let mut rest: Vec<Type> = members.iter().filter(|member| runtime_type(member) != runtime_type(tested)).cloned().collect();
+
+    match rest.len() {
+        0 => None,
+        1 => rest.pop(),
+        _ => Some(Type::Union(rest)),
+    }
+}
+
+fn assigned_names(stmt: &Stmt) -> Vec<String> {
+    match stmt {
+        Stmt::Assign(name, _)                   => vec![name.clone()],
+        Stmt::Block(stmts)                      => stmts.iter().flat_map(assigned_names).collect(),
+        Stmt::If(_, if_block, else_block)       => [assigned_names(if_block), else_block.as_deref().map(assigned_names).unwrap_or_default()].concat(),
+        Stmt::For(init, _, step, block)         => [assigned_names(init), assigned_names(step), assigned_names(block)].concat(),
+        Stmt::While(_, block)                   => assigned_names(block),
+        _ => Vec::new(),
+    }
+}
+
+// Type parameters parse as class names; this turns the ones in scope into `Type::Param`
+fn resolve_type(typename: Type, type_params: &[String]) -> Type {
+    match typename {
+        Type::Class(name) if type_params.contains(&name) => Type::Param(name),
+        Type::Array(element)    => Type::Array(resolve_type(*element, type_params).wrap()),
+        Type::Optional(inner)   => Type::Optional(resolve_type(*inner, type_params).wrap()),
+        Type::Variadic(element) => Type::Variadic(resolve_type(*element, type_params).wrap()),
+        Type::Union(members)    => Type::Union(members.into_iter().map(|member| resolve_type(member, type_params)).collect()),
+        Type::Func(params, ret) => Type::Func(params.into_iter().map(|param| resolve_type(param, type_params)).collect(), resolve_type(*ret, type_params).wrap()),
+        other                   => other,
+    }
+}
+
+fn is_inferable(typename: &Type) -> bool {
+    match typename {
+        Type::Any | Type::Unknown | Type::Void | Type::Null => false,
+        Type::Array(element)    => is_inferable(element),
+        Type::Func(params, ret) => params.iter().all(is_inferable) && (**ret == Type::Void || is_inferable(ret)),
+        _                       => true,
+    }
+}
+
+fn resolve_signature(type_params: &[String], return_type: Type, params: Vec<(Type, String, Option<Expr>)>) -> (Type, Vec<(Type, String, Option<Expr>)>) {
+    let return_type = resolve_type(return_type, type_params);
+    let params      = params.into_iter().map(|(t, name, default)| (resolve_type(t, type_params), name, default)).collect();
+
+    (return_type, params)
+}
+
+fn param_defaults(params: &[(Type, String, Option<Expr>)]) -> Defaults {
+    params.iter().map(|(_, name, default)| (name.clone(), default.clone())).collect()
+}
+
+// Values that SQF's `params` can take as a default
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Number(_) | Expr::Bool(_) | Expr::String(_) | Expr::Null(_)   => true,
+        Expr::Array(elements)                                               => elements.iter().all(is_constant),
+        Expr::UnaryOp(Operator::Neg, value, _)                              => matches!(**value, Expr::Number(_)),
+        _                                                                   => false,
+    }
+}
+
+// Type parameters the call site could not infer are left unknown, after an error saying so; the `rigid`
+// ones of the enclosing generic function stay as they are
+fn substitute(typename: &Type, bindings: &HashMap<String, Type>, rigid: &[String]) -> Type {
+    let substitute = |typename: &Type| substitute(typename, bindings, rigid);
+
+    match typename {
+        Type::Param(name)       => bindings.get(name).cloned().unwrap_or_else(|| if rigid.contains(name) { typename.clone() } else { Type::Unknown }),
+        Type::Array(element)    => Type::Array(substitute(element).wrap()),
+        Type::Optional(inner)   => Type::Optional(substitute(inner).wrap()),
+        Type::Variadic(element) => Type::Variadic(substitute(element).wrap()),
+        Type::Union(members)    => Type::Union(members.iter().map(substitute).collect()),
+        Type::Func(params, ret) => Type::Func(params.iter().map(substitute).collect(), substitute(ret).wrap()),
+        other                   => other.clone(),
     }
 }
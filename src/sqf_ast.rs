@@ -43,6 +43,10 @@ pub enum Expr {
     UnaryOp(Operator, Box<Expr>, bool),
     BinaryOp(Operator, Box<Expr>, Box<Expr>),
     FuncCall(String, Vec<Expr>),
+    UnaryCmd(String, Box<Expr>),
+    BinaryCmd(String, Box<Expr>, Box<Expr>),
+    Code(Vec<(Type, String, Option<Expr>)>, Box<Stmt>),
+    Inline(Box<Expr>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -50,14 +54,17 @@ pub enum Stmt {
     Dummy,
     Expr(Expr),
     VarDecl(String, Option<Expr>),
-    FuncDef(String, Vec<(Type, String)>, Box<Stmt>),
+    FuncDef(String, Vec<(Type, String, Option<Expr>)>, Box<Stmt>),
+    ClassDef(String, Vec<(String, Expr)>),
     Assign(String, Expr),
     Block(Vec<Stmt>),
     Program(Vec<Stmt>),
-    Return(Expr),
+    Return(Option<Expr>),
     Break,
     Continue,
     If(Expr, Box<Stmt>, Option<Box<Stmt>>),
     For(Box<Stmt>, Expr, Box<Stmt>, Box<Stmt>),
+    // `for "_i" from a to b step s`, with the end included
+    ForRange(String, Expr, Expr, Option<Expr>, Box<Stmt>),
     While(Expr, Box<Stmt>),
 }
\ No newline at end of file
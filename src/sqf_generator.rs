@@ -1,5 +1,3 @@
-use std::usize;
-
 use crate::sqf_ast::*;
 
 pub trait SQFGenerator {
@@ -18,13 +16,34 @@ impl SQFGenerator for Vec<Expr> {
     }
 }
 
-impl SQFGenerator for Vec<(Type, String)> {
+// Parameters with a default use the `[name, default, [types]]` form, which also checks the type at run time
+impl SQFGenerator for Vec<(Type, String, Option<Expr>)> {
     fn generate_sqf(&self, indent: usize, minify: bool) -> String {
-        let params = self.iter().map(|(_, s)| format!("\"{}\"", s.clone())).collect::<Vec<String>>().join(", ");
+        let params = self.iter().map(|(typename, name, default)| match (default, type_sample(typename)) {
+            (None, _)               => format!("\"{}\"", name),
+            (Some(default), None)   => format!("[\"{}\", {}]", name, default.generate_sqf(indent, minify)),
+            (Some(default), Some(sample)) => format!("[\"{}\", {}, [{}]]", name, default.generate_sqf(indent, minify), sample),
+        }).collect::<Vec<String>>().join(", ");
         format!("{}params [{}];", "    ".repeat(indent), params)
     }
 }
 
+// A value of the type, which is how `params` is told what to accept
+fn type_sample(typename: &Type) -> Option<&'static str> {
+    match typename {
+        Type::Number    => Some("0"),
+        Type::String    => Some("\"\""),
+        Type::Boolean   => Some("true"),
+        Type::Array(_)  => Some("[]"),
+        Type::Object    => Some("objNull"),
+        Type::Group     => Some("grpNull"),
+        Type::Control   => Some("controlNull"),
+        Type::HashMap   => Some("createHashMap"),
+        Type::Code      => Some("{}"),
+        _               => None,
+    }
+}
+
 impl SQFGenerator for Expr {
     fn generate_sqf(&self, indent: usize, minify: bool) -> String {
         match self {
@@ -36,16 +55,36 @@ impl SQFGenerator for Expr {
             Expr::FuncCall(name, args)              => format!("([{}] call {})", args.generate_sqf(indent, minify), name),
             Expr::UnaryOp(op, expr, is_postfix)     => {
                 if *is_postfix {
-                    format!("{}{}", expr.generate_sqf(indent, minify), op.to_string())
+                    format!("{}{}", expr.generate_sqf(indent, minify), op)
                 } else {
-                    format!("{}{}", op.to_string(), expr.generate_sqf(indent, minify))
+                    format!("{}{}", op, expr.generate_sqf(indent, minify))
                 }
             },
             Expr::BinaryOp(op, left, right)         => {
-                format!("({}{}{})", left.generate_sqf(indent, minify), op.to_string(), right.generate_sqf(indent, minify))
+                format!("({}{}{})", left.generate_sqf(indent, minify), op, right.generate_sqf(indent, minify))
             },
             Expr::ArrayAccess(array, index)         => {
-                format!("{} select {}", array, index.generate_sqf(indent, minify))
+                format!("({} select {})", array, index.generate_sqf(indent, minify))
+            }
+            Expr::UnaryCmd(cmd, arg)                => format!("({} {})", cmd, arg.generate_sqf(indent, minify)),
+            Expr::BinaryCmd(cmd, left, right)       => {
+                format!("({} {} {})", left.generate_sqf(indent, minify), cmd, right.generate_sqf(indent, minify))
+            },
+            Expr::Inline(expr)                      => format!("{{{}}}", expr.generate_sqf(indent, minify)),
+            Expr::Code(params, body)                => {
+                let (indent_str, indent_str2) = if minify {
+                    (String::new(), String::new())
+                } else {
+                    ("    ".repeat(indent), "    ".repeat(indent + 1))
+                };
+
+                let params_str = if params.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}\n", params.generate_sqf(indent + 1, minify))
+                };
+
+                format!("{{\n{}{}scopeName \"__func__\";\n{}\n{}}}", params_str, indent_str2, body.generate_sqf(indent, minify), indent_str)
             }
         }
     }
@@ -86,9 +125,19 @@ impl SQFGenerator for Stmt {
                     indent_str
                 )
             }
+            Stmt::ClassDef(name, entries)   => {
+                let entries_str = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}[\"{}\", {}]", indent_str2, key, value.generate_sqf(indent + 1, minify)))
+                    .collect::<Vec<_>>()
+                    .join(",\n");
+
+                format!("{}{}=[\n{}\n{}]", indent_str, name, entries_str, indent_str)
+            }
             Stmt::Block(stmts)              => stmts.iter().map(|stmt| format!("{};", stmt.generate_sqf(indent + 1, minify))).collect::<Vec<_>>().join("\n"),
             Stmt::Program(stmts)            => stmts.iter().map(|stmt| format!("{};", stmt.generate_sqf(indent, minify))).collect::<Vec<_>>().join("\n"),
-            Stmt::Return(expr)              => format!("{}{} breakOut \"__func__\"", indent_str, expr.generate_sqf(indent, minify)),
+            Stmt::Return(Some(expr))        => format!("{}{} breakOut \"__func__\"", indent_str, expr.generate_sqf(indent, minify)),
+            Stmt::Return(None)              => format!("{}breakOut \"__func__\"", indent_str),
             Stmt::Break                     => format!("{}break", indent_str),
             Stmt::Continue                  => format!("{}continue", indent_str),
             Stmt::If(condition, if_block, else_block) => {
@@ -112,6 +161,18 @@ impl SQFGenerator for Stmt {
                     indent_str
                 )
             },
+            Stmt::ForRange(name, from, to, step, block) => {
+                format!(
+                    "{}for \"{}\" from {} to {}{} do {{\n{}\n{}}}",
+                    indent_str,
+                    name,
+                    from.generate_sqf(indent, minify),
+                    to.generate_sqf(indent, minify),
+                    step.as_ref().map(|s| format!(" step {}", s.generate_sqf(indent, minify))).unwrap_or_default(),
+                    block.generate_sqf(indent, minify),
+                    indent_str
+                )
+            },
             Stmt::While(condition, block) => {
                 format!(
                     "{}while{{{}}}do{{\n{}\n{}}}",
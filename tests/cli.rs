@@ -0,0 +1,36 @@
+use std::process::{Command, Output};
+
+// Transpiles the source with the given flags, in a directory of its own
+fn transpile(name: &str, src: &str, flags: &[&str]) -> Output {
+    let dir = std::env::temp_dir().join(format!("dgen_cli_{}", name));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.dg"), src).unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_dGen"))
+        .current_dir(&dir)
+        .args(["-i", "main.dg", "-o", "out.sqf"])
+        .args(flags)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_later_lint_flags_win() {
+    let output = transpile("deny_then_allow", "number x = 1;", &["-D", "warnings", "-A", "unused_variable"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+
+    let output = transpile("allow_then_deny", "number x = 1;", &["-A", "unused_variable", "-D", "warnings"]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Semantic error: Unused variable 'x' [unused_variable]\n");
+
+    let output = transpile("warn_then_allow", "number x = 1;", &["-W", "unused_variable", "-A", "warnings", "-W", "unused_variable"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Warning: Unused variable 'x' [unused_variable]\n");
+}
+
+#[test]
+fn test_prototypes() {
+    let output = transpile("prototypes", "number twice(number); hint(str(twice(2))); number twice(number x) { return x * 2; };", &["-O0"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+}
@@ -1,18 +1,16 @@
-use std::f64::INFINITY;
-
 use dgen::parser::*;
 use dgen::optimizer::*;
 use dgen::dgen_ast::*;
+use dgen::generic::*;
+use dgen::boxable::*;
 
 fn test_optimizer_generic(expr: &str, expected: Expr) {
     let parsed = parse(expr.to_string()).unwrap();
     let optimized = optimize(parsed);
 
     match optimized {
-        Stmt::Block(v) => {
-            assert_eq!(v.get(0), Some(&Stmt::Expr(expected)), "Failed optimization: {:?}", expr);
-        },
-        _ => assert!(true, "Failed optimization: {:?}", expr)
+        Stmt::Program(v) => assert_eq!(v.first(), Some(&Stmt::Expr(expected)), "Failed optimization: {:?}", expr),
+        other => panic!("Expected a program, got {:?}", other),
     }
 }
 
@@ -34,7 +32,8 @@ fn test_optimizer_multiplication() {
 #[test]
 fn test_optimizer_division() {
     test_optimizer_generic("10 / 2;", Expr::Number(5.0));
-    test_optimizer_generic("10 / 0;", Expr::Number(INFINITY));
+    // SQF reports division by zero at run time, there's no value to fold it to
+    test_optimizer_program("10 / 0; 10 % 0;", "10 / 0; 10 % 0;");
 }
 
 #[test]
@@ -62,9 +61,20 @@ fn test_optimizer_nested_logical_expression() {
     test_optimizer_generic("(true || false) && (false || true);", Expr::Bool(true));
 }
 
+// Strings and numbers only mix through an explicit conversion, so the implicit form is left for the analyzer to reject
 #[test]
 fn test_optimizer_mixed_string_and_number_addition() {
-    test_optimizer_generic("\"Number: \" + 5;", Expr::String("Number: 5".to_string()));
+    test_optimizer_generic("\"Number: \" + 5;", Expr::BinaryOp(Operator::Add, Expr::String("Number: ".to_string()).wrap(), Expr::Number(5.0).wrap()));
+    test_optimizer_generic("\"Number: \" + 5 as string;", Expr::String("Number: 5".to_string()));
+    test_optimizer_generic("\"Number: \" + str(5);", Expr::String("Number: 5".to_string()));
+}
+
+#[test]
+fn test_optimizer_casts() {
+    test_optimizer_generic("\"42\" as number + 1;", Expr::Number(43.0));
+    test_optimizer_generic("parseNumber(\" 2.5 \");", Expr::Number(2.5));
+    test_optimizer_generic("true as string;", Expr::String("true".to_string()));
+    test_optimizer_generic("\"abc\" as number;", Expr::Cast(Expr::String("abc".to_string()).wrap(), Type::Number));
 }
 
 #[test]
@@ -81,4 +91,388 @@ fn test_optimizer_boolean_negation_and_comparison() {
 #[test]
 fn test_optimizer_multiple_operations() {
     test_optimizer_generic("((3 + 2) * 2 >= 10) || (false && true);", Expr::Bool(true));
-}
\ No newline at end of file
+}
+fn test_optimizer_program(src: &str, expected: &str) {
+    assert_eq!(optimize(parse(src.to_string()).unwrap()), parse(expected.to_string()).unwrap(), "Failed optimization: {:?}", src);
+}
+
+#[test]
+fn test_dead_branches() {
+    test_optimizer_program("if (true) { hint(\"a\"); } else { hint(\"b\"); };", "hint(\"a\");");
+    test_optimizer_program("if (1 > 2) { hint(\"a\"); } else { hint(\"b\"); hint(\"c\"); };", "hint(\"b\"); hint(\"c\");");
+    test_optimizer_program("if (false) { hint(\"a\"); }; hint(\"b\");", "hint(\"b\");");
+    test_optimizer_program("while (false) { hint(\"a\"); }; hint(\"b\");", "hint(\"b\");");
+    test_optimizer_program("while (time > 1) { if (!true) { hint(\"a\"); }; };", "while (time > 1) {};");
+
+    // Nothing left to run but a condition that does nothing either
+    test_optimizer_program("if (time > 1) {} else {}; hint(\"b\");", "hint(\"b\");");
+    test_optimizer_program("if (time > 1) { hint(\"a\"); } else {};", "if (time > 1) { hint(\"a\"); };");
+    test_optimizer_program("#[noinline] boolean f() { hint(\"a\"); return true; }; if (f()) {};", "#[noinline] boolean f() { hint(\"a\"); return true; }; if (f()) {};");
+}
+
+#[test]
+fn test_dead_statements_after_exit() {
+    test_optimizer_program("#[noinline] void f() { hint(\"a\"); return; hint(\"b\"); }; f();", "#[noinline] void f() { hint(\"a\"); return; }; f();");
+    test_optimizer_program("while (time > 1) { break; hint(\"a\"); };", "while (time > 1) { break; };");
+    test_optimizer_program("#[noinline] void f() { if (time > 1) { return; } else { return; }; hint(\"a\"); }; f();", "#[noinline] void f() { if (time > 1) { return; } else { return; }; }; f();");
+}
+
+#[test]
+fn test_dead_variables() {
+    test_optimizer_program("number a = 1; number b = a + 2; b = 3; hint(\"x\");", "hint(\"x\");");
+    test_optimizer_program("number a = random(1); hint(str(a));", "number a = random(1); hint(str(a));");
+    test_optimizer_program("number a = random(1) * damage(player);", "");
+    // Calls may do anything, so their results stay even when nothing reads them
+    test_optimizer_program("#[noinline] number f() { hint(\"a\"); return 1; }; number a = f();", "#[noinline] number f() { hint(\"a\"); return 1; }; number a = f();");
+    test_optimizer_program("number a = random(1); a = a + 1;", "number a = random(1); a = a + 1;");
+}
+
+#[test]
+fn test_dead_functions() {
+    test_optimizer_program("#[noinline] void f() {}; #[noinline] void g() { f(); }; #[noinline] void h() { g(); }; h();", "#[noinline] void f() {}; #[noinline] void g() { f(); }; #[noinline] void h() { g(); }; h();");
+    test_optimizer_program("void f() {}; void g() { f(); }; hint(\"a\");", "hint(\"a\");");
+    test_optimizer_program("#[noinline] void f() {}; #[export] void g() { f(); };", "#[noinline] void f() {}; #[export] void g() { f(); };");
+    test_optimizer_program("void f(); void g() { f(); }; void f() {};", "");
+    test_optimizer_program("#[noinline] number f() { return 1; }; number a = f(); if (false) { a = f(); };", "#[noinline] number f() { return 1; }; number a = f();");
+}
+
+#[test]
+fn test_constant_propagation() {
+    test_optimizer_program("number z = 1; number l = z * 3; hint(str(l));", "hint(\"3\");");
+    test_optimizer_program("string s = \"a\"; s = s + \"b\"; hint(s);", "hint(\"ab\");");
+    test_optimizer_program("bool b = false; if (b) { hint(\"a\"); };", "");
+    test_optimizer_program("number a = random(1); number b = a; hint(str(b + 1));", "number a = random(1); hint(str(a + 1));");
+    // Reassigning the source ends the copy
+    test_optimizer_program("number a = random(1); number b = a; a = 2; hint(str(b));", "number a = random(1); number b = a; a = 2; hint(str(b));");
+    // `time` has moved on by the time the copy is read
+    test_optimizer_program(
+        "number start = time; hint(\"a\"); number e = time - start; hint(str(e));",
+        "number start = time; hint(\"a\"); number e = time - start; hint(str(e));",
+    );
+}
+
+#[test]
+fn test_propagation_through_branches() {
+    test_optimizer_program("number a = 1; if (time > 1) { a = 2; }; hint(str(a));", "number a = 1; if (time > 1) { a = 2; }; hint(str(a));");
+    test_optimizer_program("number a = 1; if (time > 1) { a = 2; } else { a = 2; }; hint(str(a));", "hint(\"2\");");
+    test_optimizer_program("#[noinline] void f() { number a = 1; if (time > 1) { a = 2; return; }; hint(str(a)); }; f();", "#[noinline] void f() { if (time > 1) { return; }; hint(\"1\"); }; f();");
+}
+
+#[test]
+fn test_propagation_through_loops() {
+    test_optimizer_program("number a = 1; while (a < 10) { a = a + 1; }; hint(str(a));", "number a = 1; while (a < 10) { a = a + 1; }; hint(str(a));");
+    test_optimizer_program("number a = 1; number n = 0; while (n < 10) { n += a; };", "number n = 0; while (n < 10) { n += 1; };");
+    test_optimizer_program("number s = 0; for (number i = 0; i < 3; i += 1) { s = s + i; }; hint(str(s));", "number s = 0; for (number i = 0; i < 3; i += 1) { s = s + i; }; hint(str(s));");
+    test_optimizer_program("number a = 2; for (number i = 0; i < a; i += 1) { hint(str(a)); };", "for (number i = 0; i < 2; i += 1) { hint(\"2\"); };");
+}
+
+#[test]
+fn test_propagation_across_calls() {
+    // `f` assigns the global, so its value is unknown after any call
+    test_optimizer_program(
+        "number g = 1; #[noinline] void f() { g = 2; }; f(); hint(str(g));",
+        "number g = 1; #[noinline] void f() { g = 2; }; f(); hint(str(g));",
+    );
+    test_optimizer_program(
+        "number g = 1; #[noinline] void f() { number g = 2; hint(str(g)); }; f(); hint(str(g));",
+        "#[noinline] void f() { hint(\"2\"); }; f(); hint(\"1\");",
+    );
+    test_optimizer_program("number a = 1; hint(str(a)); a++; hint(str(a));", "number a = 1; hint(\"1\"); a++; hint(str(a));");
+}
+
+#[test]
+fn test_inlined_expressions() {
+    test_optimizer_program("number sq(number x) { return x * x; }; hint(str(sq(3)));", "hint(\"9\");");
+    test_optimizer_program("number add(number a, number b) { return a + b; }; number v = random(1); hint(str(add(v, damage(player))));", "number v = random(1); hint(str(v + damage(player)));");
+    // Recursive functions keep their calls
+    test_optimizer_program(
+        "number fib(number n) { if (n < 2) { return n; }; return fib(n - 1) + fib(n - 2); }; hint(str(fib(5)));",
+        "number fib(number n) { if (n < 2) { return n; }; return fib(n - 1) + fib(n - 2); }; hint(str(fib(5)));",
+    );
+    // The body calls code that changes the argument before reading it, so the argument is read at the call
+    let bump = "number counter = random(5); #[noinline] number bump() { counter += 1; return 0; }; number f(number x) { return bump() + x; };";
+    test_optimizer_program(
+        &format!("{} hint(str(f(counter))); number y = f(counter); hint(str(y));", bump),
+        &format!("{} hint(str(f(counter))); number y; number x_1 = counter; y = bump() + x_1; hint(str(y));", bump),
+    );
+    test_optimizer_program(
+        "number n = random(5); number f(number x) { return n++ + x; }; hint(str(f(n)));",
+        "number n = random(5); number f(number x) { return n++ + x; }; hint(str(f(n)));",
+    );
+    // The caller's own `g` would hide the global the body reads
+    test_optimizer_program(
+        "number g = random(1); number get() { return g; }; #[noinline] void h() { number g = random(2); hint(str(g + get())); }; h();",
+        "number g = random(1); number get() { return g; }; #[noinline] void h() { number g = random(2); hint(str(g + get())); }; h();",
+    );
+}
+
+#[test]
+fn test_inlined_statements() {
+    test_optimizer_program("number sq(number x) { return x * x; }; number a = sq(random(2)); hint(str(a));", "number a; number x_1 = random(2); a = x_1 * x_1; hint(str(a));");
+    test_optimizer_program(
+        "number clamp(number v) { if (v > 10) { return 10; }; return v; }; number a = clamp(random(20)); hint(str(a));",
+        "number a; number v_1 = random(20); if (v_1 > 10) { a = 10; } else { a = v_1; }; hint(str(a));",
+    );
+    // Renamed parameters don't clash with the caller's variables
+    test_optimizer_program(
+        "number sq(number x) { return x * x; }; number x_1 = random(1); number a = sq(x_1 + 1); hint(str(a + x_1));",
+        "number x_1 = random(1); number a; number x_2 = x_1 + 1; a = x_2 * x_2; hint(str(a + x_1));",
+    );
+    test_optimizer_program("void greet(string who) { hint(\"hi \" + who); }; greet(\"bob\");", "hint(\"hi bob\");");
+    // A parameter holding a function is called under its new name
+    let label = "#[noinline] string label(number n) { return str(n); };";
+    test_optimizer_program(
+        &format!("{} string apply(number x, fn(number) -> string f) {{ return f(x); }}; string s = apply(random(5), label); hint(s);", label),
+        &format!("{} string s; number x_1 = random(5); fn(number) -> string f_1 = label; s = f_1(x_1); hint(s);", label),
+    );
+}
+
+#[test]
+fn test_inline_attributes() {
+    let body = "for (number i = 0; i < n; i += 1) { hint(str(i)); hint(str(i + 1)); hint(str(i + 2)); };";
+
+    test_optimizer_program(&format!("void spam(number n) {{ {} }}; spam(3);", body), &format!("void spam(number n) {{ {} }}; spam(3);", body));
+    test_optimizer_program(
+        &format!("#[inline] void spam(number n) {{ {} }}; spam(3);", body),
+        "for (number i_1 = 0; i_1 < 3; i_1 += 1) { hint(str(i_1)); hint(str(i_1 + 1)); hint(str(i_1 + 2)); };",
+    );
+    test_optimizer_program("#[noinline] number one() { return 1; }; hint(str(one()));", "#[noinline] number one() { return 1; }; hint(str(one()));");
+}
+
+#[test]
+fn test_algebraic_identities() {
+    test_optimizer_program("x * 1; 1 * x; x + 0; x - 0; x / 1;", "x; x; x; x; x;");
+    test_optimizer_program("x * 0; x - x; count(x) - count(x);", "0; 0; 0;");
+    test_optimizer_program("!!x; !!(x > 1);", "x; x > 1;");
+    // Calls that do something, or give a different value each time, stay
+    test_optimizer_program("f() * 0; random(1) - random(1); getPos(x) - getPos(x); x++ - x++;", "f() * 0; random(1) - random(1); getPos(x) - getPos(x); x++ - x++;");
+    test_optimizer_program("random(1) * 0;", "0;");
+}
+
+#[test]
+fn test_de_morgan() {
+    test_optimizer_program("!(!a && !b); !(a || !b); !(!a || b);", "a || b; !a && b; a && !b;");
+    // Without a `!` to cancel, the negation stays outside
+    test_optimizer_program("!(a && b);", "!(a && b);");
+}
+
+#[test]
+fn test_reassociation() {
+    test_optimizer_program("(x + 2) + 3; 3 + (y + 2); (x * 2) * 4; (x - 2) + 3; (x + 5) - 3; (x - 2) - 3;", "x + 5; y + 5; x * 8; x + 1; x + 2; x - 5;");
+    test_optimizer_program("(x + 2) - 2; (s + \"a\") + \"b\";", "x; s + \"ab\";");
+}
+
+#[test]
+fn test_strength_reduction() {
+    let x = || Expr::Identifier("x".to_string()).wrap();
+
+    test_optimizer_generic("x / 2;", Expr::BinaryOp(Operator::Mul, x(), Expr::Number(0.5).wrap()));
+    test_optimizer_generic("x / 1024;", Expr::BinaryOp(Operator::Mul, x(), Expr::Number(1.0 / 1024.0).wrap()));
+    test_optimizer_program("x / 3; x / 0;", "x / 3; x / 0;");
+}
+
+#[test]
+fn test_sqf_arithmetic() {
+    let x = || Expr::Identifier("x".to_string()).wrap();
+
+    // Numbers are 32-bit floats in SQF
+    test_optimizer_generic("16777216 + 1;", Expr::Number(16777216.0));
+    test_optimizer_generic("16777217 == 16777216;", Expr::Bool(true));
+    test_optimizer_generic("1 / 3;", Expr::Number((1.0f32 / 3.0) as f64));
+    test_optimizer_program("100000000000000000000 * 100000000000000000000;", "100000000000000000000 * 100000000000000000000;");
+    test_optimizer_generic("x * 100000000000000000000 * 100000000000000000000;", Expr::BinaryOp(Operator::Mul,
+        Expr::BinaryOp(Operator::Mul, x(), Expr::Number(1e20).wrap()).wrap(), Expr::Number(1e20).wrap()));
+
+    // `mod` keeps the sign of the dividend
+    test_optimizer_generic("(0 - 7) % 3;", Expr::Number(-1.0));
+    test_optimizer_generic("7 % (0 - 3);", Expr::Number(1.0));
+    test_optimizer_generic("x / 0;", Expr::BinaryOp(Operator::Div, x(), Expr::Number(0.0).wrap()));
+}
+
+#[test]
+fn test_sqf_conversions() {
+    let string = |s: &str| Expr::String(s.to_string());
+
+    test_optimizer_generic("str(123456);", string("123456"));
+    test_optimizer_generic("str(1000000);", string("1e+06"));
+    test_optimizer_generic("str(1234567);", string("1.23457e+06"));
+    test_optimizer_generic("str(1 / 3);", string("0.333333"));
+    test_optimizer_generic("str(1 / 4);", string("0.25"));
+    test_optimizer_generic("str(1 / 100000);", string("1e-05"));
+    test_optimizer_generic("str(0 - 5);", string("-5"));
+    test_optimizer_generic("str(16777217);", string("1.67772e+07"));
+    test_optimizer_generic("parseNumber(\"2.5\");", Expr::Number(2.5));
+    test_optimizer_program("parseNumber(\"nan\"); parseNumber(\"1e40\");", "parseNumber(\"nan\"); parseNumber(\"1e40\");");
+}
+
+#[test]
+fn test_loop_invariants() {
+    test_optimizer_program(
+        "number[] arr = getPos(player); for (number i = 0; i < count(arr); i += 1) { hint(str(arr[i])); i += 1; };",
+        "number[] arr = getPos(player); any count_1 = count(arr); for (number i = 0; i < count_1; i += 1) { hint(str(arr[i])); i += 1; };",
+    );
+    test_optimizer_program(
+        "number[] arr = getPos(player); number i = 0; while (i < count(arr) - 1) { hint(str(abs(arr[0]) + i)); i++; };",
+        "number[] arr = getPos(player); number i = 0; any invariant_1 = count(arr) - 1; any abs_1 = abs(arr[0]); while (i < invariant_1) { hint(str(abs_1 + i)); i++; };",
+    );
+
+    // Inner loops go first, then what they moved out is moved out of the outer loop too
+    test_optimizer_program(
+        "number[] arr = getPos(player); for (number i = 0; i < 3; i++) { for (number j = 0; j < 3; j++) { hint(str(i + j + abs(arr[0]))); }; };",
+        "number[] arr = getPos(player); any abs_2 = abs(arr[0]); for (number i = 0; i < 3; i++) { for (number j = 0; j < 3; j++) { hint(str(i + j + abs_2)); }; };",
+    );
+
+    // A loop counting to its bound in SQF evaluates the bound once anyway
+    let counting = "number[] arr = getPos(player); for (number i = 0; i < count(arr); i++) { hint(str(arr[i])); };";
+    test_optimizer_program(counting, counting);
+}
+
+#[test]
+fn test_loop_variants() {
+    // What the loop assigns, reads of the game state, and what a function it calls may assign all stay
+    for src in [
+        "number[] arr = getPos(player); while (count(arr) > 0) { arr = []; };",
+        "number n = random(5); while (abs(n) > 1) { n--; };",
+        "while (damage(player) < 1) { hint(\"x\"); };",
+        "#[noinline] void f() { arr = []; }; number[] arr = getPos(player); while (count(arr) > 0) { f(); };",
+        "for (number i = 0; i < 3; i += 1) { number[] b = getPos(player); hint(str(count(b))); };",
+    ] {
+        test_optimizer_program(src, src);
+    }
+}
+
+#[test]
+fn test_common_subexpressions() {
+    let program = |src: &str| format!("number a = random(5); number b = random(5); {} hint(str(b));", src);
+
+    test_optimizer_program(&program("hint(str(a * b + a * b));"), &program("any common_1 = a * b; hint(str(common_1 + common_1));"));
+    test_optimizer_program(&program("hint(str(abs(a) + 1)); hint(str(abs(a) + 2));"), &program("any abs_1 = abs(a); hint(str(abs_1 + 1)); hint(str(abs_1 + 2));"));
+    test_optimizer_program(&program("hint(str(abs(a))); if (abs(a) > 1) { hint(\"x\"); };"), &program("any abs_1 = abs(a); hint(str(abs_1)); if (abs_1 > 1) { hint(\"x\"); };"));
+
+    // Larger repeats go first, and what they repeat of the rest is shared in turn
+    test_optimizer_program(
+        &program("hint(str((a * b + 1) * (a * b + 1) + a * b));"),
+        &program("any common_2 = a * b; any common_1 = common_2 + 1; hint(str(common_1 * common_1 + common_2));"),
+    );
+
+    // Functions that compute their result from their arguments alone are shared like pure builtins
+    let sq = "#[noinline] number sq(number x) { number y = x * x; return y; };";
+    test_optimizer_program(&format!("{} {}", sq, program("hint(str(sq(a) + sq(a)));")), &format!("{} {}", sq, program("any sq_1 = sq(a); hint(str(sq_1 + sq_1));")));
+}
+
+#[test]
+fn test_unshared_subexpressions() {
+    for src in [
+        // What comes in between assigns what the expression reads
+        "number a = random(5); hint(str(abs(a))); a = random(5); hint(str(abs(a)));",
+        "number a = random(5); hint(str(abs(a) + abs(a++)));",
+        // Reads of the game state and random numbers can differ from one call to the next
+        "hint(str(random(5) * 2)); hint(str(random(5) * 2));",
+        "hint(str(count(getPos(player)))); hint(str(count(getPos(player))));",
+        // Branches and loop bodies only run sometimes
+        "number a = random(5); if (time > 1) { hint(str(abs(a))); } else { hint(str(abs(a))); };",
+        // Functions that do something, or read a variable that can change
+        "#[noinline] number next(number x) { hint(\"x\"); return x + 1; }; number a = random(5); hint(str(next(a) + next(a)));",
+        "number g = random(1); #[noinline] number h(number x) { return x + g; }; number a = random(5); hint(str(h(a) + h(a))); g = 2;",
+        // Not worth a variable
+        "number a = random(5); number c = -a; number d = -a; hint(str(c + d));",
+    ] {
+        test_optimizer_program(src, src);
+    }
+}
+
+fn run_passes(manager: &PassManager, src: &str) -> Stmt {
+    manager.run(parse(src.to_string()).unwrap(), &mut |_, _, _| ())
+}
+
+#[test]
+fn test_optimization_levels() {
+    let src = "number sq(number x) { return x * x; }; number a = 2 + 3; hint(str(sq(a)));";
+
+    assert_eq!(run_passes(&PassManager::new(Level::None), src), parse(src.to_string()).unwrap());
+    assert_eq!(run_passes(&PassManager::new(Level::Basic), src), parse("number sq(number x) { return x * x; }; hint(str(sq(5)));".to_string()).unwrap());
+    assert_eq!(run_passes(&PassManager::new(Level::Full), src), parse("hint(\"25\");".to_string()).unwrap());
+
+    // Only bodies no bigger than the call are inlined for size
+    let src = "number sq(number x) { return x * x; }; number f(number x) { return x * x + x * 2; }; number a = random(1); hint(str(f(a) + sq(a)));";
+    assert_eq!(
+        run_passes(&PassManager::new(Level::Size), src),
+        parse("number f(number x) { return x * x + x * 2; }; number a = random(1); hint(str(f(a) + a * a));".to_string()).unwrap(),
+    );
+}
+
+#[test]
+fn test_selected_passes() {
+    let mut manager = PassManager::new(Level::Full);
+    manager.set_passes(vec![Pass::Fold]);
+    assert_eq!(run_passes(&manager, "number a = 2 * 3; if (false) { hint(\"a\"); };"), parse("number a = 6; if (false) { hint(\"a\"); };".to_string()).unwrap());
+
+    manager.set_passes(vec![Pass::Fold, Pass::Dce]);
+    manager.print_after(Pass::Dce);
+    let mut dumps = Vec::new();
+    manager.run(parse("number a = 2 * 3; if (false) { hint(\"a\"); };".to_string()).unwrap(), &mut |pass, round, program| dumps.push((pass, round, program.clone())));
+    assert_eq!(dumps.first(), Some(&(Pass::Dce, 1, Stmt::Program(Vec::new()))));
+
+    assert_eq!(Pass::from_name("inline"), Ok(Pass::Inline));
+    assert_eq!(Pass::from_name("gvn"), Err("Unknown optimization pass 'gvn', expected one of fold, tailcall, inline, propagate, cse, licm, dce".to_string()));
+    assert_eq!(Level::from_name("s"), Ok(Level::Size));
+    assert!(Level::from_name("3").is_err());
+}
+
+fn test_tail_calls(src: &str, expected: &str) {
+    let mut manager = PassManager::new(Level::Full);
+    manager.set_passes(vec![Pass::TailCall]);
+    assert_eq!(run_passes(&manager, src), parse(expected.to_string()).unwrap(), "Failed tail call elimination: {:?}", src);
+}
+
+#[test]
+fn test_tail_calls_become_loops() {
+    test_tail_calls(
+        "number gcd(number a, number b) { if (b == 0) { return a; }; return gcd(b, a % b); };",
+        "number gcd(number a, number b) { while (true) { if (b == 0) { return a; }; number b_1 = a % b; a = b; b = b_1; }; };",
+    );
+    test_tail_calls(
+        "void countdown(number n) { if (n > 0) { hint(str(n)); countdown(n - 1); }; };",
+        "void countdown(number n) { while (true) { if (n > 0) { hint(str(n)); n = n - 1; continue; }; return; }; };",
+    );
+    test_tail_calls(
+        "void countdown(number n) { if (n == 0) { return; }; countdown(n - 1); return; };",
+        "void countdown(number n) { while (true) { if (n == 0) { return; }; n = n - 1; }; };",
+    );
+
+    // Arguments stepping a parameter are all evaluated before any parameter changes
+    test_tail_calls(
+        "number f(number a, number b) { if (a > b) { return a; }; return f(a + random(2), b++); };",
+        "number f(number a, number b) { while (true) { if (a > b) { return a; }; number a_1 = a + random(2); number b_1 = b++; a = a_1; b = b_1; }; };",
+    );
+
+    // A result combined with one more factor on the way back is accumulated instead
+    test_tail_calls(
+        "number fact(number n) { if (n == 0) { return 1; }; return n * fact(n - 1); };",
+        "number fact(number n) { number product_1 = 1; while (true) { if (n == 0) { return product_1 * 1; }; product_1 = product_1 * n; n = n - 1; }; };",
+    );
+    test_tail_calls(
+        "number sum(number n) { if (n <= 0) { return 0; }; return sum(n - 1) + n * 2; };",
+        "number sum(number n) { number sum_1 = 0; while (true) { if (n <= 0) { return sum_1 + 0; }; sum_1 = sum_1 + n * 2; n = n - 1; }; };",
+    );
+}
+
+#[test]
+fn test_non_tail_calls() {
+    for src in [
+        // The result is used for more than one more factor
+        "number fib(number n) { if (n < 2) { return n; }; return fib(n - 1) + fib(n - 2); };",
+        "number f(number n) { if (n == 0) { return 1; }; return f(n - 1) - n; };",
+        // Something is left to do after the call
+        "void f(number n) { if (n > 0) { f(n - 1); hint(str(n)); }; };",
+        // The factor reads a variable the call could change
+        "number g = 1; number f(number n) { if (n == 0) { return 1; }; return g * f(n - 1); };",
+        // Inside a loop, `continue` would go to that loop
+        "number f(number n) { while (n > 0) { return f(n - 1); }; return 0; };",
+        // The rest of the arguments come packed in an array
+        "number f(number... rest) { if (count(rest) == 0) { return 0; }; return f(); };",
+    ] {
+        test_tail_calls(src, src);
+    }
+}
+
@@ -0,0 +1,445 @@
+use dgen::parser::*;
+use dgen::semantic_analyzer::*;
+use dgen::lint::*;
+use dgen::optimizer;
+
+fn analyze(src: &str) -> Vec<String> {
+    let mut analyzer = SemanticAnalyzer::new();
+    analyzer.analyze(parse(src.to_string()).unwrap());
+    analyzer.errors().to_vec()
+}
+
+fn warnings(src: &str) -> Vec<String> {
+    let mut analyzer = SemanticAnalyzer::new();
+    analyzer.analyze(parse(src.to_string()).unwrap());
+    analyzer.warnings().to_vec()
+}
+
+fn assert_valid(src: &str) {
+    let errors = analyze(src);
+    assert!(errors.is_empty(), "Unexpected errors for {:?}: {:?}", src, errors);
+}
+
+fn assert_error(src: &str, expected: &str) {
+    let errors = analyze(src);
+    assert!(errors.iter().any(|e| e.contains(expected)), "Expected {:?} for {:?}, got {:?}", expected, src, errors);
+}
+
+const UNIT: &str = "
+class Unit {
+    number hp = 100;
+    Unit(number hp) { self.hp = hp; };
+    void hit(number d) { self.hp -= d; };
+};
+";
+
+#[test]
+fn test_class_members() {
+    assert_valid(&format!("{} Unit u = new Unit(10); u.hit(5); number hp = u.hp;", UNIT));
+    assert_error(&format!("{} Unit u = new Unit(10); u.heal(5);", UNIT), "Class 'Unit' has no member 'heal'");
+    assert_error(&format!("{} Unit u = new Unit(10); u.hit(\"5\");", UNIT), "Argument type mismatch in 'Unit.hit'");
+    assert_error(&format!("{} Unit u = new Unit(); ", UNIT), "expects 1 arguments, got 0");
+    assert_error("number n = 5; number m = n.hp;", "Cannot access member 'hp' of non-class type Number");
+}
+
+#[test]
+fn test_class_self() {
+    assert_error("class A { void f() { self.x = 1; }; };", "Class 'A' has no member 'x'");
+    assert_error("void f() { self.x = 1; };", "Undefined variable: 'self'");
+}
+
+#[test]
+fn test_class_inheritance() {
+    let soldier = format!("{} class Soldier : Unit {{ number armor = 5; void hit(number d) {{ self.hp -= d - self.armor; }}; }};", UNIT);
+
+    assert_valid(&format!("{} Unit u = new Soldier(10); u.hit(1);", soldier));
+    assert_error(&format!("{} Soldier s = new Unit(10);", soldier), "Type mismatch in declaration of 's'");
+    assert_error("class A : B { };", "Unknown base class 'B' for 'A'");
+}
+
+#[test]
+fn test_class_override_checks() {
+    assert_error(&format!("{} class B : Unit {{ number hit(number d) {{ return d; }}; }};", UNIT), "Method 'B.hit' overrides a base method with an incompatible signature");
+    assert_error(&format!("{} class B : Unit {{ number hp; }};", UNIT), "Field 'B.hp' is already declared in a base class");
+    assert_error(&format!("{} class B : Unit {{ void hp() {{ }}; }};", UNIT), "Method 'B.hp' conflicts with an inherited field");
+}
+
+const GENERICS: &str = "
+T first<T>(T[] arr) { return arr[0]; };
+U apply<T, U>(T x, fn(T)->U f) { return f(x); };
+string label(number n) { return \"n\"; };
+number[] nums = [1, 2, 3];
+";
+
+#[test]
+fn test_generic_inference() {
+    assert_valid(&format!("{} number n = first(nums); string l = apply(5, label); string[] ls = [first([\"a\"])];", GENERICS));
+    assert_error(&format!("{} string s = first(nums);", GENERICS), "Type mismatch in declaration of 's'");
+    assert_error(&format!("{} number n = apply(5, label);", GENERICS), "Type mismatch in declaration of 'n'");
+    assert_error(&format!("{} string l = apply(\"5\", label);", GENERICS), "Argument type mismatch in 'apply'");
+}
+
+#[test]
+fn test_generic_signatures() {
+    assert_error("T make<T>() { T x; return x; }; number n = make();", "Cannot infer type parameter 'T' of 'make'");
+    assert_error("void f<T, T>(T a) { };", "Duplicate type parameter 'T'");
+    assert_error("T f(T a) { return a; };", "Unknown type 'T'");
+    assert_error("T f<T>(T a) { number n = a; return a; };", "Type mismatch in declaration of 'n'");
+
+    // Calls through a function-typed parameter keep the type parameters of the body they're in
+    let map = "U[] map<T, U>(T[] a, fn(T)->U f) { U[] out = []; number i = 0; while (i < count(a)) { out = out + [f(a[i])]; i = i + 1; }; return out; };";
+    assert_valid(&format!("{} {} string[] ls = map(nums, label);", GENERICS, map));
+    assert_error("U g<T, U>(T a, fn(T)->U f) { number n = f(a); return f(a); };", "Type mismatch in declaration of 'n'");
+}
+
+#[test]
+fn test_function_values() {
+    assert_valid(&format!("{} fn(number)->string f = label; string s = f(1);", GENERICS));
+    assert_error(&format!("{} fn(string)->string f = label;", GENERICS), "Type mismatch in declaration of 'f'");
+    assert_error("number n = 1; number m = n(2);", "'n' is not a function");
+}
+
+#[test]
+fn test_auto_inference() {
+    assert_valid("let pos = getPos(player); number[] p = pos; auto n = pos[0] + count(pos); number m = n;");
+    assert_valid("let xs = [\"a\", \"b\"]; string[] ys = xs; let x = xs[0]; string y = x;");
+    assert_error("let xs = [1, 2]; string[] ys = xs;", "Type mismatch in declaration of 'ys'");
+    assert_error("let x;", "Cannot infer the type of 'x' without an initializer; an explicit type is required");
+    assert_error("let xs = [];", "Cannot infer the type of 'xs' from Array(Any); an explicit type is required");
+    assert_error("let h = hint(\"hi\");", "Cannot infer the type of 'h' from Void");
+    assert_error("class A { let x = 5; };", "Field 'A.x' needs an explicit type");
+}
+
+#[test]
+fn test_builtins() {
+    assert_valid("number d = distance(player, player); boolean a = alive(player);");
+    assert_error("number count = 5;", "Redeclaration of 'count'");
+    assert_error("player = player;", "Cannot assign to builtin 'player'");
+    assert_error("let f = getPos;", "Builtin 'getPos' cannot be used as a value");
+}
+
+#[test]
+fn test_optional_checks() {
+    assert_valid("object? target = null; if (target != null) { number d = damage(target); };");
+    assert_valid("object? target = player; number d = damage(target);");
+    assert_valid("void f(string? s) { if (s == null) { return; }; hint(s); };");
+    assert_valid("boolean empty(string); void f(string? s) { if (s != null && empty(s)) { hint(s); }; };");
+    assert_valid("boolean empty(string); void f(string? s) { if (s == null || empty(s)) { hint(\"none\"); } else { hint(s); }; };");
+    assert_valid("void f(number? n) { if (!(n == null)) { number m = n + 1; }; };");
+    assert_error("object? target = null; number d = damage(target);", "optional value of type Optional(Object) must be checked against null first");
+    assert_error("void f(number? n) { number m = n + 1; };", "Optional value of type Optional(Number) must be checked against null before use");
+    assert_error("void f(string? s) { if (s == null) { hint(s); }; };", "must be checked against null first");
+
+    // Where the branches join, what all of those that get there established holds
+    assert_valid("number? n = null; if (n == null) { n = 3; } else { n = 4; }; number m = n + 1;");
+    assert_valid("number? n = null; if (n == null) { n = 3; }; number m = n + 1;");
+    assert_valid("void f(number? n) { if (time > 1) { n = 3; } else { if (n == null) { return; }; }; number m = n + 1; };");
+    assert_error("number? n = null; if (time > 1) { n = 3; }; number m = n + 1;", "must be checked against null before use");
+    assert_error("number? n = null; if (n == null) { n = 3; } else { n = null; }; number m = n + 1;", "must be checked against null before use");
+}
+
+#[test]
+fn test_optional_narrowing_is_invalidated() {
+    assert_error("void f(number? n) { if (n != null) { n = null; number m = n + 1; }; };", "must be checked against null before use");
+    assert_error("void f(number? n, number? k) { if (n != null) { while (true) { number m = n + 1; n = k; }; }; };", "must be checked against null before use");
+    assert_valid("void f(number? n) { if (n != null) { n = 5; number m = n + 1; }; };");
+}
+
+#[test]
+fn test_null_literal() {
+    assert_error("object target = null;", "null can only be assigned to optional types");
+    assert_error("number n = 5; boolean b = n == null;", "Only optional values can be compared with null");
+    assert_error("let x = null;", "Cannot infer the type of 'x'");
+    assert_valid("group? g; control? c = null; g = null;");
+    assert_valid("object? f() { return null; };");
+}
+
+#[test]
+fn test_casts() {
+    assert_valid("number n = 5; string s = \"Count: \" + n as string;");
+    assert_valid("string s = \"12\"; number n = s as number + 1;");
+    assert_valid("string s = str(getPos(player)); number[] codes = toArray(s); string t = toString(codes);");
+    assert_valid("class A { }; class B : A { }; B b = new B(); A a = b as A;");
+    assert_error("boolean b = 5 as boolean;", "Cannot cast Number to Boolean");
+    assert_error("number[] a = \"abc\" as number[];", "Cannot cast String to Array(Number)");
+    assert_error("void f(number? n) { string s = n as string; };", "must be checked against null before use");
+}
+
+#[test]
+fn test_mixed_string_and_number() {
+    assert_error("number n = 5; string s = \"Count: \" + n;", "strings and numbers don't mix implicitly");
+    assert_error("string s = \"5\"; number n = 1 + s;", "strings and numbers don't mix implicitly");
+}
+
+#[test]
+fn test_operator_typing() {
+    assert_valid("boolean b = 1 < 2; boolean c = b && !(3 >= 4); number n = -(1 + 2) * 3 % 2;");
+    assert_valid("string s = \"a\" + \"b\"; number[] a = [1, 2] + [3]; boolean e = s == \"ab\" || player != player;");
+    assert_error("number n = 1 < 2;", "Type mismatch in declaration of 'n'");
+    assert_error("boolean b = true + false;", "Operator '+' expects numbers, strings or arrays, got Boolean and Boolean");
+    assert_error("string s = \"a\" * 2;", "Operator '*' expects numbers, got String and Number");
+    assert_error("boolean b = \"a\" < \"b\";", "Operator '<' compares numbers, got String and String");
+    assert_error("boolean b = 1 && true;", "Operator '&&' expects booleans, got Number and Boolean");
+    assert_error("boolean b = !5;", "Operator '!' expects a boolean, got Number");
+    assert_error("number n = -\"5\";", "Operator '-' expects a number, got String");
+    assert_error("boolean b = 1 == \"1\";", "Operator '==' compares values of different types: Number and String");
+    assert_error("boolean b = true == false;", "Operator '==' cannot compare values of type Boolean");
+    assert_error("number[] a = [1] + [\"x\"];", "Cannot concatenate Array(Number) and Array(String)");
+}
+
+#[test]
+fn test_returns() {
+    assert_valid("number f(boolean b) { if (b) { return 1; } else { return 2; }; };");
+    assert_valid("number f() { while (true) { return 1; }; };");
+    assert_valid("void f(number n) { if (n > 0) { return; }; hint(\"x\"); };");
+    assert_valid("class A { A() { return; }; number f() { return 1; }; };");
+    assert_error("number f(boolean b) { if (b) { return 1; }; };", "Function 'f' must return a value of type Number on every path");
+    assert_error("number f() { while (true) { break; }; };", "must return a value of type Number on every path");
+    assert_error("number f() { return \"x\"; };", "Return type mismatch: expected Number, got String");
+    assert_error("number f() { return; };", "Function 'f' must return a value of type Number");
+    assert_error("void f() { return 1; };", "Void function 'f' cannot return a value");
+    assert_error("class A { A() { return 1; }; };", "Void function 'A.A' cannot return a value");
+    assert_error("return 1;", "'return' outside of a function");
+}
+
+#[test]
+fn test_loop_exits() {
+    assert_valid("while (true) { if (time > 5) { break; }; continue; };");
+    assert_error("break;", "'break' outside of a loop");
+    assert_error("void f() { continue; };", "'continue' outside of a loop");
+    assert_error("while (true) { void f() { break; }; };", "'break' outside of a loop");
+}
+
+#[test]
+fn test_unreachable_code() {
+    assert_eq!(warnings("#[allow(unused_function)] number f() { return 1; hint(\"x\"); };"), vec!["Unreachable code after 'return' [unreachable_code]"]);
+    assert_eq!(warnings("while (true) { break; hint(\"x\"); hint(\"y\"); };"), vec!["Unreachable code after 'break' [unreachable_code]"]);
+    assert_eq!(warnings("#[allow(unused_function)] void f(boolean b) { if (b) { return; } else { return; }; hint(\"x\"); };"), vec!["Unreachable code after 'if' [unreachable_code]"]);
+    assert!(warnings("#[allow(unused_function)] void f(boolean b) { if (b) { return; }; hint(\"x\"); };").is_empty());
+}
+
+#[test]
+fn test_definite_assignment() {
+    assert_valid("number x; x = 1; number y = x + 1;");
+    assert_valid("number x; if (time > 1) { x = 1; } else { x = 2; }; number y = x;");
+    assert_valid("number f(boolean b) { number x; if (b) { return 0; } else { x = 2; }; return x; };");
+    assert_valid("number x; void f() { hint(str(x)); }; x = 1;");
+    assert_valid("object? o; boolean b = o == null;");
+    assert_error("number x; number y = x + 1;", "'x' may be used before it is assigned");
+    assert_error("number x; x += 1;", "'x' may be used before it is assigned");
+    assert_error("number x; if (time > 1) { x = 1; }; number y = x;", "'x' may be used before it is assigned");
+    assert_error("number x; while (time < 1) { x = 1; }; number y = x;", "'x' may be used before it is assigned");
+    assert_error("number x; for (number i = 0; i < 3; i = i + 1) { x = i; }; number y = x;", "'x' may be used before it is assigned");
+    assert_error("number[] a; number n = a[0];", "'a' may be used before it is assigned");
+    assert_eq!(analyze("number x; number y = x + x;").len(), 1);
+}
+
+fn lint_with(config: LintConfig, src: &str) -> (Vec<String>, Vec<String>) {
+    let mut analyzer = SemanticAnalyzer::with_lints(config);
+    analyzer.analyze(parse(src.to_string()).unwrap());
+    (analyzer.errors().to_vec(), analyzer.warnings().to_vec())
+}
+
+#[test]
+fn test_lints() {
+    assert_eq!(warnings("number x = 1;"), vec!["Unused variable 'x' [unused_variable]"]);
+    assert_eq!(warnings("void f() {};"), vec!["Unused function 'f' [unused_function]"]);
+    assert_eq!(warnings("number f(number a, number b) { return a; }; hint(str(f(1, 2)));"), vec!["Unused parameter 'b' [unused_parameter]"]);
+    assert_eq!(warnings("number x = 1; void f(number x) { hint(str(x)); }; f(x);"), vec!["Parameter 'x' shadows an outer variable [shadowed_variable]"]);
+    assert_eq!(warnings("if (true) { hint(\"x\"); };"), vec!["Condition is always true [constant_condition]"]);
+    assert_eq!(warnings("while (false) { hint(\"x\"); };"), vec!["Loop condition is always false [constant_condition]"]);
+    assert!(warnings("while (true) { break; };").is_empty());
+    assert_eq!(warnings("number x = 1; x = x;"), vec!["'x' is assigned to itself [self_assignment]"]);
+    assert_eq!(warnings("if (time > 1) {} else { hint(\"x\"); };"), vec!["Empty 'if' block [empty_block]"]);
+    assert_eq!(warnings("hint(str(time / (1 - 1)));"), vec!["Division by zero using '/'; SQF reports an error and gives 0 [division_by_zero]"]);
+    assert_eq!(warnings("hint(str(time % 0));"), vec!["Division by zero using '%'; SQF reports an error and gives 0 [division_by_zero]"]);
+    assert!(warnings("hint(str(time / 2));").is_empty());
+    assert_error("void f(number time) { hint(str(time)); }; f(1);", "Parameter 'time' cannot shadow a builtin");
+}
+
+#[test]
+fn test_lint_levels() {
+    let mut config = LintConfig::default();
+    config.set("unused_variable", Level::Deny).unwrap();
+    assert_eq!(lint_with(config.clone(), "number x = 1;").0, vec!["Unused variable 'x' [unused_variable]"]);
+    assert_eq!(lint_with(config, "#[allow(unused_variable)] number x = 1;"), (vec![], vec![]));
+
+    let mut config = LintConfig::default();
+    config.set("warnings", Level::Allow).unwrap();
+    assert_eq!(lint_with(config, "number x = 1; x = x;"), (vec![], vec![]));
+
+    assert_eq!(lint_with(LintConfig::default(), "void f() { #[deny(unused_variable)] number x = 1; }; f();").0, vec!["Unused variable 'x' [unused_variable]"]);
+    assert_eq!(warnings("#[warn(self_assignment)] number x = 1;"), vec!["Unused variable 'x' [unused_variable]"]);
+    assert_error("#[allow(unused_varible)] number x = 1;", "Unknown lint 'unused_varible'");
+    assert_error("#[cold] number x = 1;", "Unknown attribute 'cold'");
+    assert_valid(&format!("{} #[allow(unused_variable)] Unit u = new Unit(1);", UNIT.replace("void hit", "#[allow(unused_parameter)] void hit")));
+}
+
+const FIB: &str = "number fib(number n) { if (n < 2) { return n; }; return fib(n - 1) + fib(n - 2); };";
+
+#[test]
+fn test_deep_recursion() {
+    let deep = "Call to 'fib' recurses about 4999 levels deep, more than the limit of 1000; only tail calls become loops [deep_recursion]";
+    assert_eq!(warnings(&format!("{} hint(str(fib(5000)));", FIB)), vec![deep]);
+    assert!(warnings(&format!("{} hint(str(fib(20))); hint(str(fib(time)));", FIB)).is_empty());
+    assert!(warnings(&format!("{} #[allow(deep_recursion)] number f = fib(5000); hint(str(f));", FIB)).is_empty());
+
+    // Recursion the optimizer turns into a loop can go as deep as it likes
+    assert!(warnings("number fact(number n) { if (n == 0) { return 1; }; return n * fact(n - 1); }; hint(str(fact(5000)));").is_empty());
+    assert!(warnings("void down(number n) { if (n > 0) { down(n - 1); }; }; down(5000);").is_empty());
+
+    let mut config = LintConfig::default();
+    config.set_recursion_limit(10);
+    assert_eq!(
+        lint_with(config, &format!("{} hint(str(fib(20)));", FIB)).1,
+        vec!["Call to 'fib' recurses about 19 levels deep, more than the limit of 10; only tail calls become loops [deep_recursion]"],
+    );
+
+    // At -O0 nothing turns tail calls into loops
+    let mut analyzer = SemanticAnalyzer::new();
+    analyzer.set_passes(optimizer::PassManager::new(optimizer::Level::None).passes());
+    analyzer.analyze(parse("number down(number n) { if (n == 0) { return 0; }; return down(n - 1); }; hint(str(down(50000)));".to_string()).unwrap());
+    assert_eq!(
+        analyzer.warnings(),
+        ["Call to 'down' recurses about 50001 levels deep, more than the limit of 1000; tail calls only become loops with the tailcall pass [deep_recursion]"],
+    );
+}
+
+#[test]
+fn test_lint_project_config() {
+    let config = LintConfig::from_project("[package]\nname = \"mission\"\n\n[lints]\nunused_variable = \"allow\" # noisy\nself_assignment = \"deny\"\n").unwrap();
+    assert_eq!(config.level(Lint::UnusedVariable), Level::Allow);
+    assert_eq!(config.level(Lint::SelfAssignment), Level::Deny);
+    assert_eq!(config.level(Lint::EmptyBlock), Level::Warn);
+    assert_eq!(LintConfig::from_project("[lints]\nunused = \"allow\"").unwrap_err(), "Line 2: Unknown lint 'unused'");
+    assert_eq!(LintConfig::from_project("[lints]\nempty_block = \"forbid\"").unwrap_err(), "Line 2: Unknown lint level 'forbid'");
+}
+
+#[test]
+fn test_shadowing() {
+    assert_valid("number x = 1; if (time > 1) { string x = \"a\"; hint(x); }; x = 2;");
+    assert_valid("for (number i = 0; i < 3; i += 1) { hint(str(i)); }; for (number i = 0; i < 3; i += 1) { hint(str(i)); };");
+    assert_valid("void f(number x) { if (x > 1) { number x = 2; hint(str(x)); }; }; f(1);");
+    assert_error("number x = 1; number x = 2;", "Redeclaration of 'x'");
+    assert_error("if (time > 1) { number x = 1; string x = \"a\"; };", "Redeclaration of 'x'");
+    assert_error("for (number i = 0; i < 3; i += 1) {}; i = 1;", "Undefined variable: 'i'");
+    assert_error("if (time > 1) { number time = 1; };", "Variable 'time' cannot shadow a builtin");
+    assert_error("void f(number a, number a) {}; f(1, 2);", "Duplicate parameter 'a'");
+    assert_eq!(warnings("number x = 1; if (time > x) { number x = 2; hint(str(x)); };"), vec!["Variable 'x' shadows an outer variable [shadowed_variable]"]);
+}
+
+#[test]
+fn test_forward_references() {
+    assert_valid("number n = twice(2); number twice(number x) { return x * 2; };");
+    assert_valid("boolean isEven(number n) { if (n == 0) { return true; }; return isOdd(n - 1); }; boolean isOdd(number n) { if (n == 0) { return false; }; return isEven(n - 1); }; hint(str(isEven(4)));");
+    assert_valid("number f(number); number g() { return f(1); }; number f(number x) { return x; }; hint(str(g()));");
+    assert_error("number f(string); number f(number x) { return x; }; hint(str(f(1)));", "Prototype of 'f' does not match its definition: declared as (String) -> Number, defined as (Number) -> Number");
+    assert_error("void f() { number g(); number g(number x) { return x; }; hint(str(g(1))); }; f();", "Prototype of 'g' does not match its definition: declared as () -> Number, defined as (Number) -> Number");
+    assert_error("void f() {}; void f() {}; f();", "Redeclaration of 'f'");
+    assert_error("void f() { g(); void g() {}; }; f();", "Function 'g' is not defined");
+}
+
+const CLAMP: &str = "
+number clamp(number v, number lo, number hi) { if (v < lo) { return lo; }; if (v > hi) { return hi; }; return v; };
+number[] clamp(number[] v, number lo, number hi) { return v; };
+";
+
+#[test]
+fn test_overloading() {
+    assert_valid(&format!("{} number a = clamp(5, 0, 3); number[] b = clamp([1, 2], 0, 3);", CLAMP));
+    assert_valid("void show(number n) { hint(str(n)); }; void show(string s) { hint(s); }; show(1); show(\"a\");");
+    assert_valid("void f() { void g(number n) {}; void g(string s) {}; g(1); g(\"a\"); }; f();");
+    assert_error(&format!("{} number a = clamp([1, 2], 0, 3);", CLAMP), "Type mismatch in declaration of 'a'");
+    assert_error(&format!("{} clamp(\"x\", 0, 3);", CLAMP), "No overload of 'clamp' takes (String, Number, Number); candidates are (Number, Number, Number) -> Number, (Array(Number), Number, Number) -> Array(Number)");
+    assert_error("void f(number n) {}; void f(number m) {}; f(1);", "Redeclaration of 'f'");
+    assert_error("void f(number n) {}; number f(number m) { return m; }; f(1);", "Redeclaration of 'f'");
+    assert_error("void f(number? n) {}; void f(string? s) {}; f(null);", "Ambiguous call to 'f' with argument types (Null)");
+    assert_error("void f(number n) {}; void f(string s) {}; auto g = f;", "Overloaded function 'f' cannot be used as a value");
+    assert_error("void f(number n) {}; void f(string s) {}; void f(boolean); f(1);", "Prototype of 'f' matches none of its overloads");
+}
+
+const SPAWN: &str = "void spawn(string kind, number amount = 1, boolean hidden = false) { hint(kind + str(amount) + str(hidden)); };";
+
+#[test]
+fn test_default_and_named_arguments() {
+    assert_valid(&format!("{} spawn(\"a\"); spawn(\"b\", 2); spawn(\"c\", hidden: true); spawn(hidden: true, kind: \"d\");", SPAWN));
+    assert_valid("class Squad { number size; Squad(number size = 4) { self.size = size; }; void grow(number by = 1) { self.size += by; }; }; Squad s = new Squad(); s.grow(by: 2);");
+    assert_valid("void f(object? target = null) { hint(str(target == null)); }; f();");
+    assert_error(&format!("{} spawn();", SPAWN), "Function 'spawn' expects 1 to 3 arguments, got 0");
+    assert_error(&format!("{} spawn(\"a\", 1, true, 2);", SPAWN), "Function 'spawn' expects 1 to 3 arguments, got 4");
+    assert_error(&format!("{} spawn(amount: 2);", SPAWN), "Missing argument for parameter 'kind' of 'spawn'");
+    assert_error(&format!("{} spawn(\"a\", size: 2);", SPAWN), "Function 'spawn' has no parameter 'size'");
+    assert_error(&format!("{} spawn(\"a\", amount: 2, amount: 3);", SPAWN), "Parameter 'amount' of 'spawn' is given more than once");
+    assert_error(&format!("{} spawn(kind: \"a\", 2);", SPAWN), "Positional argument after a named one in call to 'spawn'");
+    assert_error(&format!("{} spawn(\"a\", amount: \"2\");", SPAWN), "Argument type mismatch in 'spawn'");
+    assert_error("hint(text: \"a\");", "Function 'hint' does not take named arguments");
+    assert_error("void f(number a = 1, number b) {}; f(1, 2);", "Parameter 'b' of 'f' needs a default value, as the parameters before it have one");
+    assert_error("void f(number a = \"1\") {}; f();", "Type mismatch in default value of parameter 'a' of 'f'");
+    assert_error("number x = 1; void f(number a = x) {}; f();", "Default value of parameter 'a' of 'f' must be a constant");
+}
+
+#[test]
+fn test_variadic_arguments() {
+    const SUM: &str = "number sum(number... values) { number total = 0; for (number i = 0; i < count(values); i += 1) { total += values[i]; }; return total; };";
+
+    assert_valid(&format!("{} number a = sum(); number b = sum(1); number c = sum(1, 2, 3);", SUM));
+    assert_valid("void log(string fmt, any... args) { diag_log(format(fmt, count(args))); }; log(\"a\"); log(\"%1 %2\", 1, \"b\");");
+    assert_valid("string s = format(\"%1 and %2\", 1, true);");
+    assert_error(&format!("{} number a = sum(1, \"2\");", SUM), "Argument type mismatch in 'sum'");
+    assert_error(&format!("{} string a = sum(1);", SUM), "Type mismatch");
+    assert_error("void log(string fmt, any... args) {}; log();", "Function 'log' expects at least 1 arguments, got 0");
+    assert_error("void log(string fmt, any... args) {}; log(\"a\", args: 1);", "Variadic parameter 'args' of 'log' can't be passed by name");
+    assert_error("void f(number... a, number b) {}; f(1, 2);", "Only the last parameter of 'f' can be variadic");
+    assert_error("void f(number... a = [1]) {}; f();", "Variadic parameter 'a' of 'f' cannot have a default value");
+    assert_error("void f(number... a) { number b = a; }; f();", "Type mismatch");
+    assert_error("format(1);", "Argument type mismatch in 'format'");
+
+    // A rest parameter passed on stands for the arguments it collected
+    assert_valid(&format!("{} number avg(number... values) {{ return sum(values) / count(values); }}; number a = avg(1, 2);", SUM));
+    assert_error(&format!("{} number f(string... names) {{ return sum(names); }}; number a = f(\"x\");", SUM), "Argument type mismatch in 'sum': expected Number, got String");
+    assert_error(&format!("{} number[] v = [1]; number a = sum(v);", SUM), "Argument type mismatch in 'sum': expected Number, got Array(Number)");
+}
+
+#[test]
+fn test_union_types() {
+    const SHOW: &str = "void show(number | string v) { if (v is string) { hint(v); } else { hint(str(v + 1)); }; };";
+
+    assert_valid(&format!("{} show(1); show(\"a\");", SHOW));
+    assert_valid("void f(number | string v) { if (!(v is number)) { hint(v); }; };");
+    assert_valid("void f(number | string | object v) { if (v is object || v is number) { return; }; hint(v); };");
+    assert_valid("void f(number | string v) { if (v is number && v > 1) { hint(str(v)); }; };");
+    assert_valid("number | string v = 1; number n = v + 1; v = \"a\"; string s = v;");
+    assert_valid("any v = 1; if (v is number[]) { number n = v[0]; };");
+    assert_error(&format!("{} show(true);", SHOW), "Argument type mismatch in 'show'");
+    assert_error("void f(number | string v) { number n = v + 1; };", "Operator '+' expects numbers, strings or arrays");
+    assert_error("void f(number | string v) { string s = v; };", "Type mismatch in declaration of 's'");
+    assert_error("void f(number[] | string[] v) { if (v is number[]) {}; };", "'is Array(Number)' cannot tell Array(Number) and Array(String) apart at run time");
+    assert_error("void f(number | string v) { if (v is number?) {}; };", "cannot be tested with 'is'");
+    assert_error("number | number v = 1;", "Type Number appears more than once in a union");
+    assert_error("number | void v = 1;", "Type Void cannot be part of a union");
+    assert!(warnings("void f(number | string v) { if (v is boolean) { hint(\"?\"); }; };").iter().any(|w| w.contains("Type test is always false")));
+    assert!(warnings("void f(number v) { if (v is number) { hint(str(v)); }; };").iter().any(|w| w.contains("Type test is always true")));
+}
+
+#[test]
+fn test_unknown_types() {
+    // Errors aren't repeated for everything that uses the expression that caused them
+    assert_eq!(analyze("auto x = y; number n = x + 1; string s = x;").len(), 1);
+    assert_eq!(analyze("number n = missing(1) * 2;").len(), 1);
+}
+
+#[test]
+fn test_exported_functions() {
+    assert!(warnings("#[export] void api() {};").is_empty());
+    assert!(warnings("void helper() {};").iter().any(|w| w.contains("Unused function 'helper'")));
+    assert_error("#[export] number x = 1;", "Only functions can be exported");
+    assert_error("#[export(now)] void api() {};", "Attribute 'export' takes no arguments");
+}
+
+#[test]
+fn test_inline_attributes() {
+    assert_valid("#[inline] void f() {}; #[noinline] void g() {}; f(); g();");
+    assert_error("#[inline] number x = 1;", "Attribute 'inline' only applies to functions");
+    assert_error("#[inline] #[noinline] void f() {}; f();", "A function can't be both 'inline' and 'noinline'");
+    assert_error("#[noinline(always)] void f() {}; f();", "Attribute 'noinline' takes no arguments");
+}
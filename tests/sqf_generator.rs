@@ -0,0 +1,220 @@
+use dgen::parser::*;
+use dgen::semantic_analyzer::*;
+use dgen::sqf_ast;
+use dgen::dgen_ast::Stmt;
+use dgen::sqf_generator::*;
+use dgen::optimizer::{Level, PassManager};
+use dgen::transform::*;
+
+fn generate(src: &str) -> String {
+    let sqf: sqf_ast::Stmt = parse(src.to_string()).unwrap().transform();
+    sqf.generate_sqf(0, false)
+}
+
+// Some lowerings depend on types the analyzer fills in
+fn generate_analyzed(src: &str) -> String {
+    let mut analyzer = SemanticAnalyzer::new();
+    let program = analyzer.analyze(parse(src.to_string()).unwrap());
+    assert!(analyzer.errors().is_empty(), "Unexpected errors for {:?}: {:?}", src, analyzer.errors());
+
+    let sqf: sqf_ast::Stmt = program.transform();
+    sqf.generate_sqf(0, false)
+}
+
+fn generate_optimized(src: &str) -> String {
+    let mut analyzer = SemanticAnalyzer::new();
+    let program = analyzer.analyze(parse(src.to_string()).unwrap());
+    let program = PassManager::new(Level::Full).run(program, &mut |_, _, _| ());
+
+    let sqf: sqf_ast::Stmt = program.transform();
+    sqf.generate_sqf(0, false)
+}
+
+fn assert_generates(src: &str, expected: &str) {
+    let code = generate(src);
+    assert!(code.contains(expected), "Expected {:?} in output of {:?}:\n{}", expected, src, code);
+}
+
+#[test]
+fn test_class_declaration() {
+    let src = "class Unit { number hp; void hit(number d) { self.hp -= d; }; }; class Soldier : Unit { };";
+
+    assert_generates(src, "_Unit=[\n    [\"#type\", \"Unit\"],");
+    assert_generates(src, "(_self set [\"hp\", 0]);");
+    assert_generates(src, "[\"hit\", {\n        params [\"_d\"];");
+    assert_generates(src, "(_self set [\"hp\", ((_self get \"hp\")-_d)]);");
+    assert_generates(src, "[\"#base\", _Unit],");
+    assert_generates(src, "(_self call [\"__init_Unit\", []]);");
+}
+
+#[test]
+fn test_class_constructor_and_destructor() {
+    let src = "class Unit { string name; Unit(string n) { self.name = n; }; ~Unit() { self.name = \"\"; }; };";
+
+    assert_generates(src, "[\"#create\", {\n        params [\"_n\"];\n        scopeName \"__func__\";\n        (_self call [\"__init\", []]);");
+    assert_generates(src, "[\"#delete\", {");
+}
+
+#[test]
+fn test_class_instances() {
+    assert_generates("Unit u = new Unit(1, 2);", "private _u=(createHashMapObject [_Unit, [1, 2]])");
+    assert_generates("u.hit(5);", "(_u call [\"hit\", [5]])");
+    assert_generates("number hp = u.hp;", "private _hp=(_u get \"hp\")");
+}
+
+#[test]
+fn test_generic_erasure() {
+    assert_generates("T first<T>(T[] arr) { return arr[0]; };", "_first={\n    params [\"_arr\"];");
+    assert_generates("number n = first(nums);", "private _n=([_nums] call _first)");
+}
+
+#[test]
+fn test_builtin_commands() {
+    assert_generates("let pos = getPos(player);", "private _pos=(getPos player)");
+    assert_generates("number d = distance(player, unit);", "private _d=(player distance _unit)");
+    assert_generates("number n = pos[0] + 1;", "private _n=((_pos select 0)+1)");
+}
+
+#[test]
+fn test_null_values() {
+    let code = generate_analyzed("object? o; group? g = null; control? c = null; string? s = null; number? n;");
+
+    assert!(code.contains("private _o=objNull"), "{}", code);
+    assert!(code.contains("private _g=grpNull"), "{}", code);
+    assert!(code.contains("private _c=controlNull"), "{}", code);
+    assert!(code.contains("private _s=nil"), "{}", code);
+    assert!(code.contains("private _n;"), "{}", code);
+}
+
+#[test]
+fn test_null_checks() {
+    let code = generate_analyzed("object? o = null; string? s = null; boolean a = o == null; boolean b = s != null;");
+
+    assert!(code.contains("private _a=(isNull _o)"), "{}", code);
+    assert!(code.contains("private _b=!(isNil {_s})"), "{}", code);
+}
+
+#[test]
+fn test_guarded_operands() {
+    // SQF only skips the right side of `&&` and `||` when it is code
+    let code = generate_analyzed("void f(number? n, number | string v) { if (n != null && n > 2) { hint(\"a\"); }; if (n == null || n < 2) { hint(\"b\"); }; if (v is number && v > 1) { hint(\"c\"); }; hint(str(time > 2 && n == null)); }; f(null, 1);");
+    assert!(code.contains("if((!(isNil {_n})&&{(_n>2)}))then{"), "{}", code);
+    assert!(code.contains("if(((isNil {_n})||{(_n<2)}))then{"), "{}", code);
+    assert!(code.contains("if(((_v isEqualType 0)&&{(_v>1)}))then{"), "{}", code);
+    assert!(code.contains("(hint (str ((time>2)&&(isNil {_n}))));"), "{}", code);
+}
+
+#[test]
+fn test_casts() {
+    let code = generate_analyzed("number n = 5; string s = n as string; number m = s as number; string t = s as string;");
+
+    assert!(code.contains("private _s=(str _n)"), "{}", code);
+    assert!(code.contains("private _m=(parseNumber _s)"), "{}", code);
+    assert!(code.contains("private _t=_s"), "{}", code);
+}
+
+#[test]
+fn test_bare_return() {
+    assert_generates("void f() { return; };", "    breakOut \"__func__\";");
+}
+
+#[test]
+fn test_shadowing() {
+    let code = generate_analyzed("number x = 1; if (time > x) { number x = 2; x += 1; hint(str(x)); }; hint(str(x));");
+    assert!(code.contains("private __x_1=2;\n    __x_1=(__x_1+1);\n    (hint (str __x_1));"), "{}", code);
+    assert!(code.ends_with("(hint (str _x));"), "{}", code);
+
+    let code = generate_analyzed("number x = 1; void f() { number x = 2; hint(str(x)); }; f(); hint(str(x));");
+    assert!(code.contains("private _x=2;"), "{}", code);
+}
+
+#[test]
+fn test_forward_calls() {
+    let code = generate_analyzed("number n = twice(2); number twice(number x) { return x * 2; };");
+    assert!(code.starts_with("_twice={"), "{}", code);
+    assert!(code.ends_with("private _n=([2] call _twice);"), "{}", code);
+
+    // Prototypes leave nothing behind
+    let code = generate_analyzed("number twice(number); hint(str(twice(2))); number twice(number x) { return x * 2; };");
+    assert_eq!(code, "_twice={\n    params [\"_x\"];\n    scopeName \"__func__\";\n    (_x*2) breakOut \"__func__\";\n};\n(hint (str ([2] call _twice)));");
+}
+
+#[test]
+fn test_overloads() {
+    let code = generate_analyzed("void show(number n) { hint(str(n)); }; void show(number[] v) { hint(str(v)); }; show(1); show([1]);");
+    assert!(code.contains("_show={"), "{}", code);
+    assert!(code.contains("__show_number_array={"), "{}", code);
+    assert!(code.ends_with("([1] call _show);\n([[1]] call __show_number_array);"), "{}", code);
+}
+
+#[test]
+fn test_default_arguments() {
+    let code = generate_analyzed("void spawn(string kind, number amount = 1, object? near = null) { hint(kind + str(amount)); }; spawn(\"a\"); spawn(near: player, kind: \"b\");");
+    assert!(code.contains("params [\"_kind\", [\"_amount\", 1, [0]], [\"_near\", objNull, [objNull]]];"), "{}", code);
+    assert!(code.ends_with("([\"a\", 1, objNull] call _spawn);\n([\"b\", 1, player] call _spawn);"), "{}", code);
+}
+
+#[test]
+fn test_variadic_arguments() {
+    let code = generate_analyzed("void log(string fmt, any... args) { diag_log(count(args)); }; log(\"a\"); log(\"%1 %2\", 1, \"b\"); hint(format(\"%1\", 2));");
+    assert!(code.contains("params [\"_fmt\"];\n    scopeName \"__func__\";\n    private _args=(_this select [1, (count _this)]);"), "{}", code);
+    assert!(code.contains("([\"a\"] call _log);\n([\"%1 %2\", 1, \"b\"] call _log);"), "{}", code);
+    assert!(code.ends_with("(hint (format [\"%1\", 2]));"), "{}", code);
+
+    // A wrapper passes its own rest parameter on as the rest of the arguments
+    let code = generate_analyzed("void log(string fmt, any... args) { hint(format(fmt, args)); }; void warn(string fmt, any... args) { log(\"! \" + fmt, args); log(fmt, 1, args); }; warn(\"%1\", 2);");
+    assert!(code.contains("(hint (format ([_fmt]+_args)));"), "{}", code);
+    assert!(code.contains("(([(\"! \"+_fmt)]+_args) call _log);\n    (([_fmt, 1]+_args) call _log);"), "{}", code);
+}
+
+#[test]
+fn test_type_tests() {
+    let code = generate_analyzed("void f(number | string | object v) { if (v is string) { hint(v); }; if (v is number) { hint(str(v)); }; if (v is object) { hint(str(alive(v))); }; }; f(1);");
+    assert!(code.contains("if((_v isEqualType \"\"))then{"), "{}", code);
+    assert!(code.contains("if((_v isEqualType 0))then{"), "{}", code);
+    assert!(code.contains("if((_v isEqualType objNull))then{"), "{}", code);
+}
+
+#[test]
+fn test_counting_loops() {
+    assert_generates("for (number i = 0; i < 10; i++) { hint(str(i)); };", "for \"_i\" from 0 to 9 do {\n    (hint (str _i));\n}");
+    assert_generates("for (number i = 1; i <= n; i += 2) { hint(str(i)); };", "for \"_i\" from 1 to _n step 2 do {");
+    assert_generates("for (number i = 0; i < count(arr); i++) { hint(str(arr[i])); };", "for \"_i\" from 0 to ((count _arr)-1) do {");
+    assert_generates("number i = 0; i++; i--;", "_i=(_i+1);\n_i=(_i-1);");
+
+    // Calls in the body can only change the variables functions assign without declaring them
+    assert_generates("void log(number v) { hint(str(v)); }; for (number i = 0; i < count(arr); i++) { log(arr[i]); };", "for \"_i\" from 0 to ((count _arr)-1) do {");
+    // A variable only ever holding a count is as whole as the count
+    assert_generates("number n = count(arr); for (number i = 0; i < n; i++) { hint(str(i)); };", "for \"_i\" from 0 to (_n-1) do {");
+}
+
+#[test]
+fn test_optimized_counting_loops() {
+    // LICM leaves the bound where the loop evaluates it once anyway, calls in the body or not
+    let src = "#[noinline] void log(number v) { hint(str(v)); }; number[] arr = getPos(player); for (number i = 0; i < count(arr); i++) { log(arr[i]); };";
+    let code = generate_optimized(src);
+    assert!(code.contains("for \"_i\" from 0 to ((count _arr)-1) do {"), "Expected a counting loop in output of {:?}:\n{}", src, code);
+}
+
+#[test]
+fn test_generic_for_loops() {
+    // The body assigns the counter or the bound, the count may not be whole, or the counter goes down
+    for src in [
+        "for (number i = 0; i < 10; i++) { i += 1; };",
+        "for (number i = 0; i < n; i++) { n -= 1; };",
+        "for (number i = 0; i < n; i++) { hint(str(i)); };",
+        "for (number i = time; i < 10; i++) { hint(str(i)); };",
+        "for (number i = 10; i < 20; i = i - 1) { hint(str(i)); };",
+        "void f() { n = 1; }; for (number i = 0; i <= n; i++) { f(); };",
+        "number n = count(arr); n = time; for (number i = 0; i < n; i++) { hint(str(i)); };",
+    ] {
+        assert_generates(src, "for [{private _i=");
+    }
+
+    // A loop lowered on its own knows nothing about the program it came from, whatever was lowered before
+    generate("number n = count(arr); for (number i = 0; i < n; i++) { hint(str(i)); };");
+    let Stmt::Program(stmts) = parse("for (number i = 0; i < n; i++) { hint(str(i)); };".to_string()).unwrap() else { unreachable!() };
+    let sqf: sqf_ast::Stmt = stmts[0].transform();
+    assert!(sqf.generate_sqf(0, false).starts_with("for [{private _i="), "{}", sqf.generate_sqf(0, false));
+    assert_generates("for (number i = 0; i < 10; i++) { i += 1; };", "{(_i<10)},{_i=(_i+1)}] do {");
+}